@@ -1,7 +1,6 @@
-use fourteen_screws::{ Camera, Scene, Renderer };
+use fourteen_screws::{ ByteBuffer, Camera, Colour, PixelFormat, PointLight, Scene, Renderer, Viewmodel };
 use fourteen_screws::trig;
 
-use serde_json;
 use wasm_bindgen::prelude::*;
 extern crate web_sys;
 
@@ -10,12 +9,19 @@ mod player;
 const PLAYER_MARGIN: i32     = 28;
 const PLAYER_MOVE_SPEED: i32 = 8;
 const PLAYER_TURN_SPEED: i32 = trig::ANGLE_5;
+const PLAYER_LOOK_SPEED: i32 = 4;
+
+const MUZZLE_FLASH_RADIUS: i32 = 256;
 
 #[wasm_bindgen]
 pub struct FourteenScrewsDemo {
 	scene:  Scene,
 	player: player::Player,
 	renderer: Renderer,
+	viewmodel: Option<Viewmodel>,
+	speed: i32,
+	time: u32,
+	pending: f64, // milliseconds elapsed too short to have been passed on to the scene yet
 }
 
 #[wasm_bindgen]
@@ -44,19 +50,87 @@ impl FourteenScrewsDemo {
 		self.player.turn_right();
 	}
 
+	pub fn player_look_up(&mut self) {
+		self.player.look_up(self.renderer.config().height);
+	}
+
+	pub fn player_look_down(&mut self) {
+		self.player.look_down(self.renderer.config().height);
+	}
+
+	pub fn player_use(&mut self) {
+		if !self.player.use_door(&mut self.scene) {
+			self.player.push_wall(&mut self.scene);
+		}
+	}
+
+	// switches palette for effects such as damage flashes, when the level draws with palettes
+	pub fn set_palette(&mut self, index: usize) -> bool {
+		self.renderer.set_palette(index)
+	}
+
+	pub fn player_fire(&mut self) {
+		if let Some(viewmodel) = self.viewmodel.as_mut() {
+			viewmodel.fire(self.time);
+		}
+	}
+
+	pub fn update(&mut self, elapsed: f64) {
+		// the scene moves on in whole milliseconds, so carry what is left over to the next frame
+		let elapsed_ms = elapsed + self.pending;
+		self.pending   = elapsed_ms.fract();
+		self.scene.update(elapsed_ms as u32);
+
+		// how fast the player is moving, in world units a second
+		let moved = self.player.take_moved();
+		if elapsed >= 1.0 { self.speed = (moved as f64 * 1000.0 / elapsed) as i32; }
+	}
+
 	pub fn load_level(json_str: &str) -> FourteenScrewsDemo {
 		let json: serde_json::Value = serde_json::from_str(json_str).ok().unwrap();
 		
-		let scene    = Scene::try_from(&json["scene"]).ok().unwrap();
-		let camera   = Camera::try_from(&json["camera"]).ok().unwrap();
-		let renderer = Renderer::try_from(&json["renderer"]).ok().unwrap();
+		let scene      = Scene::try_from(&json["scene"]).ok().unwrap();
+		let mut camera = Camera::try_from(&json["camera"]).ok().unwrap();
+		let renderer   = Renderer::try_from(&json["renderer"]).ok().unwrap();
+
+		// the player starts looking straight ahead unless the level says otherwise
+		if json["camera"]["horizon"].is_null() {
+			camera.set_horizon(renderer.config().horizon);
+		}
 
-		let player = player::Player::new(camera, PLAYER_MOVE_SPEED, PLAYER_TURN_SPEED, PLAYER_MARGIN);
+		// levels may give the player something to hold
+		let viewmodel = if json["viewmodel"].is_object() { Some(Viewmodel::try_from(&json["viewmodel"]).ok().unwrap()) } else { None };
+
+		let player = player::Player::new(camera, PLAYER_MOVE_SPEED, PLAYER_TURN_SPEED, PLAYER_LOOK_SPEED, PLAYER_MARGIN);
+
+		FourteenScrewsDemo { scene, player, renderer, viewmodel, speed: 0, time: 0, pending: 0.0 }
+	}
 
-		FourteenScrewsDemo { scene, player, renderer }
+	pub fn width(&self) -> i32 {
+		self.renderer.config().width
 	}
 
-	pub fn render(&mut self, buf: &mut[u8]) {
-		self.renderer.render(buf, &self.scene, &self.player.camera);
+	pub fn height(&self) -> i32 {
+		self.renderer.config().height
+	}
+
+	pub fn render(&mut self, buf: &mut[u8], time: f64) {
+		let (width, height) = (self.width(), self.height());
+		let mut buf = ByteBuffer::new(buf, width, height, width as usize * 4, PixelFormat::Rgba8).unwrap();
+		self.time = time as u32;
+
+		// firing lights up the area around the player for as long as the firing frames play
+		self.scene.clear_point_lights();
+		if self.viewmodel.as_ref().is_some_and(|viewmodel| viewmodel.is_firing(self.time)) {
+			let camera = &self.player.camera;
+			let flash  = PointLight { shadows: true, ..PointLight::new(camera.x(), camera.y(), MUZZLE_FLASH_RADIUS, Colour::new(255, 200, 120, 255), 192) };
+			self.scene.add_point_light(flash);
+		}
+
+		self.renderer.render(&mut buf, &self.scene, &self.player.camera, self.time);
+
+		if let Some(viewmodel) = self.viewmodel.as_ref() {
+			viewmodel.render(&mut buf, self.renderer.textures(), self.time, self.speed);
+		}
 	}
 }
\ No newline at end of file
@@ -17,12 +17,14 @@ pub struct Player {
 	pub camera: Camera,
 	move_speed: i32,
 	rotate_speed: i32,
+	look_speed: i32,
 	margin: i32,
+	moved: i32,
 }
 
 impl Player {
-	pub fn new(camera: Camera, move_speed: i32, rotate_speed: i32, margin: i32) -> Player {
-		Player { camera, move_speed, rotate_speed, margin }
+	pub fn new(camera: Camera, move_speed: i32, rotate_speed: i32, look_speed: i32, margin: i32) -> Player {
+		Player { camera, move_speed, rotate_speed, look_speed, margin, moved: 0 }
 	}
 
 	fn translate(&mut self, mut direction: i32, amount: i32, scene: &Scene) -> HitResult {
@@ -85,6 +87,45 @@ impl Player {
 			}
 		}
 
+		// doors sit across the middle of their cell and block movement until fully open.
+		// Check the cell the player is in and the one they are moving into, but let them
+		// move away from a door they are already too close to
+		for (cell_x, cell_y) in [(grid_x, grid_y), (x1 / fourteen_screws::TILE_SIZE, y1 / fourteen_screws::TILE_SIZE)] {
+			if let Tile::Door(door) = scene.x_wall(cell_x, cell_y) {
+				let door_x = cell_x * fourteen_screws::TILE_SIZE + half_tile;
+				let closer = (x1 - door_x).abs() < (xp - door_x).abs();
+				if !door.is_passable() && ((closer && (x1 - door_x).abs() < self.margin) || (xp < door_x) != (x1 < door_x)) {
+					x1 = xp;
+					hit_result = HitResult::SlideX;
+				}
+			}
+
+			if let Tile::Door(door) = scene.y_wall(cell_x, cell_y) {
+				let door_y = cell_y * fourteen_screws::TILE_SIZE + half_tile;
+				let closer = (y1 - door_y).abs() < (yp - door_y).abs();
+				if !door.is_passable() && ((closer && (y1 - door_y).abs() < self.margin) || (yp < door_y) != (y1 < door_y)) {
+					y1 = yp;
+					hit_result = HitResult::SlideY;
+				}
+			}
+		}
+
+		// push walls which are sliding block movement wherever they currently are
+		for block in scene.push_walls() {
+			let (left, top, right, bottom) = block.bounds();
+			let inside = |x: i32, y: i32| x > left - self.margin && x < right + self.margin && y > top - self.margin && y < bottom + self.margin;
+
+			if inside(x1, yp) {
+				x1 = xp;
+				hit_result = HitResult::SlideX;
+			}
+
+			if inside(x1, y1) {
+				y1 = yp;
+				hit_result = HitResult::SlideY;
+			}
+		}
+
 		// A wall or object hasn't been hit yet. We must look further.
 		// The current grid square will be divided into four regions:
 		// A = top left; B = top right; C = bottom left; D = bottom right
@@ -116,15 +157,13 @@ impl Player {
 
 					// check adjacent y wall (above)
 					if let Tile::Surface(wall) = scene.y_wall(grid_x - 1, grid_y) {
-						if !wall.passable && x1 < x_left + self.margin {
-							if y1 < y_top + self.margin {
-								if yp > y_top + (self.margin - 1) {
-									y1 = yp;
-									hit_result = HitResult::SlideY;
-								} else {
-									x1 = xp;
-									hit_result = HitResult::SlideX;
-								}
+						if !wall.passable && x1 < x_left + self.margin && y1 < y_top + self.margin {
+							if yp > y_top + (self.margin - 1) {
+								y1 = yp;
+								hit_result = HitResult::SlideY;
+							} else {
+								x1 = xp;
+								hit_result = HitResult::SlideX;
 							}
 						}
 					}
@@ -135,30 +174,26 @@ impl Player {
 					
 					// check adjacent x wall (to right)
 					if let Tile::Surface(wall) = scene.x_wall(grid_x + 1, grid_y - 1) {
-						if !wall.passable && y1 < y_top + self.margin {
-							if x1 > x_right - self.margin {
-								if xp < x_right - (self.margin - 1) {
-									x1 = xp;
-									hit_result = HitResult::SlideX;
-								} else {
-									y1 = yp;
-									hit_result = HitResult::SlideY;
-								}
+						if !wall.passable && y1 < y_top + self.margin && x1 > x_right - self.margin {
+							if xp < x_right - (self.margin - 1) {
+								x1 = xp;
+								hit_result = HitResult::SlideX;
+							} else {
+								y1 = yp;
+								hit_result = HitResult::SlideY;
 							}
 						}
 					}
 
 					// check adjacent y wall (above)
 					if let Tile::Surface(wall) = scene.y_wall(grid_x + 1, grid_y) {
-						if !wall.passable && x1 > x_right - self.margin {
-							if y1 < y_top + self.margin {
-								if yp < y_top + (self.margin - 1) {
-									y1 = yp;
-									hit_result = HitResult::SlideY;
-								} else {
-									x1 = xp;
-									hit_result = HitResult::SlideX;
-								}
+						if !wall.passable && x1 > x_right - self.margin && y1 < y_top + self.margin {
+							if yp < y_top + (self.margin - 1) {
+								y1 = yp;
+								hit_result = HitResult::SlideY;
+							} else {
+								x1 = xp;
+								hit_result = HitResult::SlideX;
 							}
 						}
 					}
@@ -171,30 +206,26 @@ impl Player {
 					
 					// check adjacent x wall (to left)
 					if let Tile::Surface(wall) = scene.x_wall(grid_x, grid_y + 1) {
-						if !wall.passable && y1 > y_bottom - self.margin {
-							if x1 < x_left + self.margin {
-								if xp > x_left + (self.margin - 1) {
-									x1 = xp;
-									hit_result = HitResult::SlideX;
-								} else {
-									y1 = yp;
-									hit_result = HitResult::SlideY;
-								}
+						if !wall.passable && y1 > y_bottom - self.margin && x1 < x_left + self.margin {
+							if xp > x_left + (self.margin - 1) {
+								x1 = xp;
+								hit_result = HitResult::SlideX;
+							} else {
+								y1 = yp;
+								hit_result = HitResult::SlideY;
 							}
 						}
 					}
 					
 					// check adjacent y wall (below)
 					if let Tile::Surface(wall) = scene.y_wall(grid_x - 1, grid_y + 1) {
-						if !wall.passable && x1 < x_left + self.margin {
-							if y1 > y_bottom - self.margin {
-								if yp < y_bottom - (self.margin - 1) {
-									y1 = yp;
-									hit_result = HitResult::SlideY;
-								} else {
-									x1 = xp;
-									hit_result = HitResult::SlideX;
-								}
+						if !wall.passable && x1 < x_left + self.margin && y1 > y_bottom - self.margin {
+							if yp < y_bottom - (self.margin - 1) {
+								y1 = yp;
+								hit_result = HitResult::SlideY;
+							} else {
+								x1 = xp;
+								hit_result = HitResult::SlideX;
 							}
 						}
 					}
@@ -205,30 +236,26 @@ impl Player {
 					
 					// check adjacent x wall (to right)
 					if let Tile::Surface(wall) = scene.x_wall(grid_x + 1, grid_y + 1) {
-						if !wall.passable && y1 > y_bottom - self.margin {
-							if x1 > x_right - self.margin {
-								if xp < x_right - (self.margin - 1) {
-									x1 = xp;
-									hit_result = HitResult::SlideX;
-								} else {
-									y1 = yp;
-									hit_result = HitResult::SlideY;
-								}
+						if !wall.passable && y1 > y_bottom - self.margin && x1 > x_right - self.margin {
+							if xp < x_right - (self.margin - 1) {
+								x1 = xp;
+								hit_result = HitResult::SlideX;
+							} else {
+								y1 = yp;
+								hit_result = HitResult::SlideY;
 							}
 						}
 					}
 
 					// check adjacent y wall (below)
 					if let Tile::Surface(wall) = scene.y_wall(grid_x + 1, grid_y + 1) {
-						if !wall.passable && x1 > x_right - self.margin {
-							if y1 > y_bottom - self.margin {
-								if yp < y_bottom - (self.margin - 1) {
-									y1 = yp;
-									hit_result = HitResult::SlideY;
-								} else {
-									x1 = xp;
-									hit_result = HitResult::SlideX;
-								}
+						if !wall.passable && x1 > x_right - self.margin && y1 > y_bottom - self.margin {
+							if yp < y_bottom - (self.margin - 1) {
+								y1 = yp;
+								hit_result = HitResult::SlideY;
+							} else {
+								x1 = xp;
+								hit_result = HitResult::SlideX;
 							}
 						}
 					}
@@ -244,27 +271,54 @@ impl Player {
 			hit_result = HitResult::WallY;
 		}
 
+		self.moved += (((x1 - xp) * (x1 - xp) + (y1 - yp) * (y1 - yp)) as f64).sqrt() as i32;
 		self.camera.move_to(x1, y1);
 
 		hit_result
 	}
 
 	pub fn forward(&mut self, scene: &Scene) -> HitResult {
-		return self.translate(self.camera.angle(), self.move_speed, scene);
+		self.translate(self.camera.angle(), self.move_speed, scene)
 	}
 
 	pub fn back(&mut self, scene: &Scene) -> HitResult {
-		return self.translate(self.camera.angle() + trig::ANGLE_180, self.move_speed, scene);
+		self.translate(self.camera.angle() + trig::ANGLE_180, self.move_speed, scene)
 	}
 
 	pub fn strafe_left(&mut self, scene: &Scene) -> HitResult {
-		return self.translate(self.camera.angle() - trig::ANGLE_90, self.move_speed, scene);
+		self.translate(self.camera.angle() - trig::ANGLE_90, self.move_speed, scene)
 	}
 
 	pub fn strafe_right(&mut self, scene: &Scene) -> HitResult {
-		return self.translate(self.camera.angle() + trig::ANGLE_90, self.move_speed, scene);
+		self.translate(self.camera.angle() + trig::ANGLE_90, self.move_speed, scene)
 	}	
 
+	// toggle the door in the cell in front of the player, or failing that the one they stand in
+	pub fn use_door(&self, scene: &mut Scene) -> bool {
+		let reach = fourteen_screws::TILE_SIZE.to_fp();
+		let x = self.camera.x() + maths::mul(trig::cos(self.camera.angle()), reach).to_i32();
+		let y = self.camera.y() + maths::mul(trig::sin(self.camera.angle()), reach).to_i32();
+
+		scene.toggle_door(x / fourteen_screws::TILE_SIZE, y / fourteen_screws::TILE_SIZE)
+			|| scene.toggle_door(self.camera.x() / fourteen_screws::TILE_SIZE, self.camera.y() / fourteen_screws::TILE_SIZE)
+	}
+
+	// push the wall in front of the player along whichever axis they are facing closest to
+	pub fn push_wall(&self, scene: &mut Scene) -> bool {
+		let cos = trig::cos(self.camera.angle());
+		let sin = trig::sin(self.camera.angle());
+		let (dir_x, dir_y) = if cos.abs() > sin.abs() { (cos.signum(), 0) } else { (0, sin.signum()) };
+
+		let x = self.camera.x() / fourteen_screws::TILE_SIZE + dir_x;
+		let y = self.camera.y() / fourteen_screws::TILE_SIZE + dir_y;
+		scene.push_wall(x, y, dir_x, dir_y)
+	}
+
+	// distance travelled since this was last asked, which sets how much the held weapon sways
+	pub fn take_moved(&mut self) -> i32 {
+		std::mem::take(&mut self.moved)
+	}
+
 	pub fn turn_left(&mut self) {
 		self.camera.rotate(-self.rotate_speed);
 	}
@@ -272,4 +326,18 @@ impl Player {
 	pub fn turn_right(&mut self) {
 		self.camera.rotate(self.rotate_speed);
 	}
+
+	// looking up moves the horizon down the display, keeping it away from the top and bottom edges
+	pub fn look_up(&mut self, height: i32) {
+		self.look(self.look_speed, height);
+	}
+
+	pub fn look_down(&mut self, height: i32) {
+		self.look(-self.look_speed, height);
+	}
+
+	fn look(&mut self, distance: i32, height: i32) {
+		self.camera.pitch(distance);
+		self.camera.set_horizon(self.camera.horizon().clamp(height / 10, height * 9 / 10));
+	}
 }
\ No newline at end of file
@@ -1,10 +1,10 @@
 module.exports = {
-  camera: { x: 128, y: 128, angle: 0, horizon: 100 },
+  camera: { x: 128, y: 128, angle: 0 },
   scene: {
   	width: 5,
   	height: 5,
-  	x_walls: [ 4, 4, 66, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 4, 66, 4, 4 ],
-  	y_walls: [ 4, 0, 0, 0, 4, 4, 0, 0, 0, 4, 66, 0, 0, 0, 66, 4, 0, 0, 0, 4, 4, 0, 0, 0, 4 ],
+  	y_walls: [ 4, 4, 66, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 4, 66, 4, 4 ],
+  	x_walls: [ 4, 0, 0, 0, 4, 4, 0, 0, 0, 4, 66, 0, 0, 0, 66, 4, 0, 0, 0, 4, 4, 0, 0, 0, 4 ],
   	ceiling: [ 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24, 24 ],
   	floor  : [ 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43, 43 ]
   },
@@ -6,10 +6,24 @@ let level = require('./demo-level');
 const demo = FourteenScrewsDemo.load_level(JSON.stringify(level));
 
 let canvas   = document.getElementById("canvas");
+canvas.width  = demo.width();
+canvas.height = demo.height();
+
 let context  = canvas.getContext("2d", { willReadFrequently: true });
 let keystate = {}
 
-document.addEventListener('keydown', (event) => { keystate[event.code] = true; }, false);
+document.addEventListener('keydown', (event) => {
+	// using a door happens once per key press rather than every frame the key is held
+	if ((event.code === 'Space' || event.code === 'KeyE') && !event.repeat) {
+		demo.player_use();
+	}
+
+	if ((event.code === 'ControlLeft' || event.code === 'KeyF') && !event.repeat) {
+		demo.player_fire();
+	}
+
+	keystate[event.code] = true;
+}, false);
 document.addEventListener('keyup', (event) => { keystate[event.code] = false; }, false);
 
 window.mobileAndTabletCheck = function() {
@@ -72,10 +86,10 @@ max of last 100 = ${Math.round(max)}
 	}
 }
 
-function render() {
-	context.clearRect(0, 0, 320, 200);
-	var image = context.getImageData(0, 0, 320, 200);
-	demo.render(image.data);
+function render(time) {
+	context.clearRect(0, 0, canvas.width, canvas.height);
+	var image = context.getImageData(0, 0, canvas.width, canvas.height);
+	demo.render(image.data, time);
 	context.putImageData(image, 0, 0);
 }
 
@@ -96,13 +110,13 @@ function events() {
 		demo.player_strafe_right();
 	}
 
-	// if (keystate['ArrowUp'] || [ "N", "NW", "NE" ].includes(keystate['joystick-right'])) {
-	// 	demo.player_look_up();
-	// }
+	if (keystate['ArrowUp'] || [ "N", "NW", "NE" ].includes(keystate['joystick-right'])) {
+		demo.player_look_up();
+	}
 
-	// if (keystate['ArrowDown'] || [ "S", "SW", "SE" ].includes(keystate['joystick-right'])) {
-	// 	demo.player_look_down();
-	// }
+	if (keystate['ArrowDown'] || [ "S", "SW", "SE" ].includes(keystate['joystick-right'])) {
+		demo.player_look_down();
+	}
 
 	if (keystate['ArrowLeft'] || [ "W", "SW", "NW" ].includes(keystate['joystick-right'])) {
 		demo.player_turn_left();
@@ -113,10 +127,14 @@ function events() {
 	}
 }
 
-function tick() {
+let lastTick = performance.now();
+
+function tick(time) {
 	fps.render();
 	events();
-	render();	
+	demo.update(Math.max(0, time - lastTick));
+	lastTick = time;
+	render(time);	
 	requestAnimationFrame(tick);	
 }
 
@@ -1,7 +1,25 @@
 mod raycast;
 mod renderer;
 mod camera;
+mod config;
+mod viewport;
+mod lighting;
+mod animation;
+mod framebuffer;
+mod palette;
+mod overlay;
+mod automap;
+mod viewmodel;
 
 pub use crate::render::camera::*;
 pub use crate::render::raycast::*;
 pub use crate::render::renderer::*;
+pub use crate::render::config::*;
+pub use crate::render::viewport::*;
+pub use crate::render::lighting::*;
+pub use crate::render::animation::*;
+pub use crate::render::framebuffer::*;
+pub use crate::render::palette::*;
+pub use crate::render::overlay::*;
+pub use crate::render::automap::*;
+pub use crate::render::viewmodel::*;
@@ -0,0 +1,65 @@
+use crate::scene::texture_id;
+use serde_json;
+
+// a sequence of textures shown one after another, each for the same length of time
+pub struct Animation {
+	frames: Vec<u32>,
+	duration: u32,
+}
+
+impl Animation {
+	pub fn new(frames: Vec<u32>, duration: u32) -> Result<Animation, &'static str> {
+		if frames.is_empty() {
+			return Err("Animation must have at least one frame");
+		}
+
+		if duration == 0 {
+			return Err("Animation frame duration must be positive");
+		}
+
+		Ok(Animation { frames, duration })
+	}
+
+	pub fn frames(&self) -> &[u32] {
+		&self.frames
+	}
+
+	// time taken to show every frame once
+	pub fn length(&self) -> u32 {
+		self.duration * self.frames.len() as u32
+	}
+
+	// texture shown at time in milliseconds, the animation loops forever
+	pub fn frame_at(&self, time: u32) -> u32 {
+		self.frames[(time / self.duration) as usize % self.frames.len()]
+	}
+}
+
+impl TryFrom<&serde_json::Value> for Animation {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		let frames = json["frames"].as_array().ok_or("Animation must have a list of frames")?.iter()
+			.map(texture_id)
+			.collect::<Option<Vec<u32>>>()
+			.ok_or("Animation texture ids start at 1")?;
+		let duration = json["duration"].as_u64().ok_or("Animation must have a frame duration")? as u32;
+		Animation::new(frames, duration)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_frame_at() {
+		let animation = Animation::new(vec![4, 7, 2], 100).unwrap();
+
+		assert_eq!(4, animation.frame_at(0));
+		assert_eq!(4, animation.frame_at(99));
+		assert_eq!(7, animation.frame_at(100));
+		assert_eq!(2, animation.frame_at(250));
+		assert_eq!(4, animation.frame_at(300));
+	}
+}
@@ -0,0 +1,477 @@
+use crate::{ Camera };
+use crate::render::{ Colour, FrameBuffer, Overlay, Rect, Renderer };
+use crate::scene::{ Scene, Tile };
+use crate::trig;
+use serde_json;
+use shared::consts;
+use shared::fp;
+use shared::fp::{ ToFixedPoint, FromFixedPoint };
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+// the cells of a scene which have been on screen. Columns are drawn independently of each other,
+// so cells are marked through a shared reference
+pub struct SeenCells {
+	width: i32,
+	height: i32,
+	cells: Vec<AtomicBool>,
+}
+
+impl SeenCells {
+	pub fn new(width: i32, height: i32) -> SeenCells {
+		let cells = (0..(width * height).max(0)).map(|_| AtomicBool::new(false)).collect();
+		SeenCells { width, height, cells }
+	}
+
+	pub fn width(&self) -> i32 {
+		self.width
+	}
+
+	pub fn height(&self) -> i32 {
+		self.height
+	}
+
+	pub fn is_seen(&self, x: i32, y: i32) -> bool {
+		self.idx(x, y).is_some_and(|idx| self.cells[idx].load(Ordering::Relaxed))
+	}
+
+	pub fn mark(&self, x: i32, y: i32) {
+		if let Some(idx) = self.idx(x, y) { self.cells[idx].store(true, Ordering::Relaxed); }
+	}
+
+	// marks every cell a ray passes over before travelling a fixed point distance, stopping
+	// short of the wall it ends at so the cell behind it is not marked
+	pub fn mark_ray(&self, origin_x: i32, origin_y: i32, direction: i32, distance: i32) {
+		let (cos, sin) = (trig::cos(direction), trig::sin(direction));
+		let end        = fp::sub(distance, 1.to_fp());
+		let step       = (consts::TILE_SIZE >> 2).to_fp();
+
+		let mut travelled = 0;
+		loop {
+			let x = fp::add(origin_x, fp::mul(travelled, cos)).to_i32().div_euclid(consts::TILE_SIZE);
+			let y = fp::add(origin_y, fp::mul(travelled, sin)).to_i32().div_euclid(consts::TILE_SIZE);
+			if self.idx(x, y).is_none() { break; }
+
+			self.mark(x, y);
+			if travelled >= end { break; }
+			travelled = std::cmp::min(fp::add(travelled, step), end);
+		}
+	}
+
+	pub fn clear(&mut self) {
+		for cell in self.cells.iter_mut() {
+			*cell.get_mut() = false;
+		}
+	}
+
+	fn idx(&self, x: i32, y: i32) -> Option<usize> {
+		if x < 0 || x >= self.width || y < 0 || y >= self.height { return None; }
+		Some((x + y * self.width) as usize)
+	}
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Orientation {
+	NorthUp,
+	PlayerUp, // turns with the camera, so the way it faces is always up the screen
+}
+
+impl TryFrom<&serde_json::Value> for Orientation {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		match json.as_str() {
+			None | Some("north_up") => Ok(Orientation::NorthUp),
+			Some("player_up")       => Ok(Orientation::PlayerUp),
+			Some(_)                 => Err("Automap orientation must be one of north_up or player_up"),
+		}
+	}
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Corner {
+	TopLeft,
+	TopRight,
+	BottomLeft,
+	BottomRight,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Placement {
+	FullScreen,
+	Inset { corner: Corner, width: i32, height: i32, margin: i32 },
+}
+
+impl Placement {
+	// the area of a display covered by the map
+	pub fn area(&self, display_width: i32, display_height: i32) -> Rect {
+		match *self {
+			Placement::FullScreen => Rect::new(0, 0, display_width, display_height),
+			Placement::Inset { corner, width, height, margin } => {
+				let left = margin;
+				let top  = margin;
+				let right  = display_width - margin - width;
+				let bottom = display_height - margin - height;
+				match corner {
+					Corner::TopLeft     => Rect::new(left, top, width, height),
+					Corner::TopRight    => Rect::new(right, top, width, height),
+					Corner::BottomLeft  => Rect::new(left, bottom, width, height),
+					Corner::BottomRight => Rect::new(right, bottom, width, height),
+				}
+			},
+		}
+	}
+}
+
+impl TryFrom<&serde_json::Value> for Placement {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		// either "full_screen", or an inset such as {"corner": "top_right", "width": 160, "height": 120}
+		if json.is_null() || json.as_str() == Some("full_screen") {
+			return Ok(Placement::FullScreen);
+		}
+
+		let corner = match json["corner"].as_str() {
+			Some("top_left")     => Corner::TopLeft,
+			Some("top_right")    => Corner::TopRight,
+			Some("bottom_left")  => Corner::BottomLeft,
+			Some("bottom_right") => Corner::BottomRight,
+			_                    => return Err("Automap inset corner must be one of top_left, top_right, bottom_left or bottom_right"),
+		};
+
+		let width  = json["width"].as_i64().filter(|width| *width > 0).ok_or("Automap inset must have a positive width")? as i32;
+		let height = json["height"].as_i64().filter(|height| *height > 0).ok_or("Automap inset must have a positive height")? as i32;
+		let margin = json["margin"].as_i64().map_or(0, |margin| margin as i32);
+		Ok(Placement::Inset { corner, width, height, margin })
+	}
+}
+
+// a top down map of the cells the renderer has shown, centred on the camera and drawn over the
+// display like the rest of the overlay
+pub struct Automap {
+	scale: i32, // pixels across each tile
+	orientation: Orientation,
+	placement: Placement,
+	pub textured: bool,             // draw seen floors with their textures rather than a flat colour
+	pub background: Option<Colour>, // drawn behind the map, where nothing has been seen
+	pub floor_colour: Colour,
+	pub wall_colour: Colour,
+	pub door_colour: Colour,
+	pub player_colour: Colour,
+}
+
+impl Automap {
+	pub fn new(scale: i32, orientation: Orientation, placement: Placement) -> Result<Automap, &'static str> {
+		if scale <= 0 {
+			return Err("Automap scale must be a positive value");
+		}
+
+		Ok(Automap {
+			scale,
+			orientation,
+			placement,
+			textured: false,
+			background: Some(Colour::new(0, 0, 0, 0xC0)),
+			floor_colour: Colour::new(0x30, 0x30, 0x30, 0xFF),
+			wall_colour: Colour::new(0xE0, 0xE0, 0xE0, 0xFF),
+			door_colour: Colour::new(0xE0, 0xA0, 0x20, 0xFF),
+			player_colour: Colour::new(0x40, 0xE0, 0x40, 0xFF),
+		})
+	}
+
+	pub fn set_scale(&mut self, scale: i32) {
+		self.scale = scale.max(1);
+	}
+
+	pub fn set_orientation(&mut self, orientation: Orientation) {
+		self.orientation = orientation;
+	}
+
+	pub fn set_placement(&mut self, placement: Placement) {
+		self.placement = placement;
+	}
+
+	// only the cells the renderer has drawn so far are shown, and only walls bordering them
+	pub fn render<B: FrameBuffer>(&self, buf: &mut B, scene: &Scene, camera: &Camera, renderer: &Renderer) {
+		let area    = self.placement.area(buf.width(), buf.height());
+		let view    = MapView::new(self, area, camera);
+		let seen    = renderer.seen();
+		let mut overlay = Overlay::new(buf);
+		overlay.set_clip(area);
+
+		if let Some(background) = self.background {
+			overlay.fill(area, background);
+		}
+
+		// the map may be turned, so each pixel looks up the point of the floor beneath it
+		let textures = renderer.textures();
+		for y in area.y..area.y + area.height {
+			for x in area.x..area.x + area.width {
+				let (world_x, world_y) = view.to_world(x, y);
+				let (cell_x, cell_y)   = (world_x.div_euclid(consts::TILE_SIZE), world_y.div_euclid(consts::TILE_SIZE));
+				if !seen.is_seen(cell_x, cell_y) { continue; }
+
+				let colour = match scene.floor(cell_x, cell_y) {
+					Tile::Surface(tile) if self.textured => {
+						let column = (world_x & (consts::TILE_SIZE - 1)) as usize * textures.texture_width() / consts::TILE_SIZE as usize;
+						let row    = (world_y & (consts::TILE_SIZE - 1)) as usize * textures.texture_height() / consts::TILE_SIZE as usize;
+						textures.texel(tile.texture, column as i32, row as i32)
+					},
+					_ => self.floor_colour,
+				};
+				overlay.plot(x, y, colour);
+			}
+		}
+
+		// walls lie along the grid lines between cells, or are set back from them into a cell
+		for cell_y in 0..=scene.height() {
+			for cell_x in 0..=scene.width() {
+				if let Some((offset, colour)) = self.wall_style(scene.y_wall(cell_x, cell_y)) {
+					if seen.is_seen(cell_x, cell_y) || seen.is_seen(cell_x, cell_y - 1) {
+						let y = cell_y * consts::TILE_SIZE + offset;
+						overlay.line(view.to_screen(cell_x * consts::TILE_SIZE, y), view.to_screen((cell_x + 1) * consts::TILE_SIZE, y), colour);
+					}
+				}
+
+				if let Some((offset, colour)) = self.wall_style(scene.x_wall(cell_x, cell_y)) {
+					if seen.is_seen(cell_x, cell_y) || seen.is_seen(cell_x - 1, cell_y) {
+						let x = cell_x * consts::TILE_SIZE + offset;
+						overlay.line(view.to_screen(x, cell_y * consts::TILE_SIZE), view.to_screen(x, (cell_y + 1) * consts::TILE_SIZE), colour);
+					}
+				}
+			}
+		}
+
+		// the camera, with lines along the edges of its field of view
+		let (x, y) = view.to_screen(camera.x(), camera.y());
+		overlay.fill(Rect::new(x - 1, y - 1, 3, 3), self.player_colour);
+		for angle in [camera.angle() - (camera.fov() >> 1), camera.angle() + (camera.fov() >> 1)] {
+			let angle  = angle.rem_euclid(trig::ANGLE_360);
+			let length = 2 * consts::TILE_SIZE;
+			let end_x  = camera.x() + fp::mul(length.to_fp(), trig::cos(angle)).to_i32();
+			let end_y  = camera.y() + fp::mul(length.to_fp(), trig::sin(angle)).to_i32();
+			overlay.line((x, y), view.to_screen(end_x, end_y), self.player_colour);
+		}
+	}
+
+	// where a wall is drawn within its cell and in what colour, if there is a wall at all
+	fn wall_style(&self, tile: &Tile) -> Option<(i32, Colour)> {
+		match tile {
+			Tile::Surface(wall) => Some((wall.offset, self.wall_colour)),
+			Tile::Door(_)       => Some((consts::TILE_SIZE >> 1, self.door_colour)),
+			_                   => None,
+		}
+	}
+}
+
+impl TryFrom<&serde_json::Value> for Automap {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		let scale       = json["scale"].as_i64().map_or(8, |scale| scale as i32);
+		let orientation = Orientation::try_from(&json["orientation"])?;
+		let placement   = Placement::try_from(&json["placement"])?;
+		let mut automap = Automap::new(scale, orientation, placement)?;
+
+		automap.textured = json["textured"].as_bool().unwrap_or(false);
+
+		// the background may be turned off with null, otherwise every colour is optional
+		if let Some(background) = json.get("background") {
+			automap.background = if background.is_null() { None } else { Some(Colour::try_from(background)?) };
+		}
+
+		for (key, colour) in [
+			("floor_colour", &mut automap.floor_colour),
+			("wall_colour", &mut automap.wall_colour),
+			("door_colour", &mut automap.door_colour),
+			("player_colour", &mut automap.player_colour),
+		] {
+			if !json[key].is_null() { *colour = Colour::try_from(&json[key])?; }
+		}
+
+		Ok(automap)
+	}
+}
+
+// converts between points in the world and on the display for a map centred on the camera. The
+// world is turned so that the direction the map faces is up the screen, which is north, towards
+// the top of the scene, unless the map turns with the camera
+struct MapView {
+	centre_x: i32,
+	centre_y: i32,
+	origin_x: i32,
+	origin_y: i32,
+	cos: i32,
+	sin: i32,
+	scale: i64,
+}
+
+impl MapView {
+	fn new(automap: &Automap, area: Rect, camera: &Camera) -> MapView {
+		let (cos, sin) = match automap.orientation {
+			Orientation::NorthUp  => (0, -(1.to_fp())),
+			Orientation::PlayerUp => (trig::cos(camera.angle()), trig::sin(camera.angle())),
+		};
+
+		MapView {
+			centre_x: area.x + (area.width >> 1),
+			centre_y: area.y + (area.height >> 1),
+			origin_x: camera.x(),
+			origin_y: camera.y(),
+			cos,
+			sin,
+			scale: automap.scale as i64,
+		}
+	}
+
+	// the distance ahead of the camera along the facing direction becomes up the screen and the
+	// distance to its right becomes right
+	fn to_screen(&self, x: i32, y: i32) -> (i32, i32) {
+		let (dx, dy) = ((x - self.origin_x).to_fp(), (y - self.origin_y).to_fp());
+		let forward  = fp::add(fp::mul(dx, self.cos), fp::mul(dy, self.sin)) as i64;
+		let side     = fp::sub(fp::mul(dy, self.cos), fp::mul(dx, self.sin)) as i64;
+		let tile     = consts::TILE_SIZE as i64;
+		(self.centre_x + ((side * self.scale / tile) >> 16) as i32, self.centre_y - ((forward * self.scale / tile) >> 16) as i32)
+	}
+
+	fn to_world(&self, x: i32, y: i32) -> (i32, i32) {
+		let tile    = consts::TILE_SIZE as i64;
+		let side    = ((((x - self.centre_x) as i64) << 16) * tile / self.scale) as i32;
+		let forward = ((((self.centre_y - y) as i64) << 16) * tile / self.scale) as i32;
+		let dx      = fp::sub(fp::mul(forward, self.cos), fp::mul(side, self.sin));
+		let dy      = fp::add(fp::mul(forward, self.sin), fp::mul(side, self.cos));
+		(self.origin_x + dx.to_i32(), self.origin_y + dy.to_i32())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::render::{ ByteBuffer, PixelFormat, RenderConfig, TextureMap };
+	use std::fs;
+	use std::path::Path;
+
+	fn load_scene() -> Scene {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(fname).unwrap()).unwrap();
+		Scene::try_from(&json).unwrap()
+	}
+
+	// draws the map into a 48x48 display after the renderer has drawn the scene from the camera,
+	// which marks the cells it saw
+	fn render_map(automap: &Automap, scene: &Scene, camera: &Camera) -> Vec<u8> {
+		let mut renderer = Renderer::new(RenderConfig::new(64, 40, 20).unwrap(), TextureMap::new(64, 64, vec![255; 64 * 64 * 4 * 66]));
+		let mut pixels   = vec![0; 64 * 40 * 4];
+		renderer.render(&mut ByteBuffer::new(&mut pixels, 64, 40, 64 * 4, PixelFormat::Rgba8).unwrap(), scene, camera, 0);
+
+		let mut bytes = vec![0; 48 * 48 * 4];
+		automap.render(&mut ByteBuffer::new(&mut bytes, 48, 48, 48 * 4, PixelFormat::Rgba8).unwrap(), scene, camera, &renderer);
+		bytes
+	}
+
+	fn pixel(bytes: &[u8], x: i32, y: i32) -> (u8, u8, u8, u8) {
+		let idx = ((x + y * 48) * 4) as usize;
+		(bytes[idx], bytes[idx + 1], bytes[idx + 2], bytes[idx + 3])
+	}
+
+	#[test]
+	fn test_mark_ray() {
+		let seen = SeenCells::new(4, 4);
+
+		// from the middle of the first cell straight along the top row, ending on the far wall
+		let half = consts::TILE_SIZE >> 1;
+		seen.mark_ray(half.to_fp(), half.to_fp(), trig::ANGLE_0, (2 * consts::TILE_SIZE + half).to_fp());
+
+		assert!(seen.is_seen(0, 0) && seen.is_seen(1, 0) && seen.is_seen(2, 0));
+		assert!(!seen.is_seen(3, 0));
+		assert!(!seen.is_seen(0, 1));
+	}
+
+	#[test]
+	fn test_render_seen_cells() {
+		let scene = load_scene();
+
+		// four pixels to a tile with nothing behind the map, centred on the camera in the middle
+		// of cell 2, 2 looking east
+		let mut automap = Automap::new(4, Orientation::NorthUp, Placement::FullScreen).unwrap();
+		automap.background = None;
+		let camera = Camera::new(160, 160, trig::ANGLE_0, 20);
+		let bytes  = render_map(&automap, &scene, &camera);
+
+		// the cell ahead is drawn, with the wall on its far side
+		assert_eq!(automap.floor_colour.tuple(), pixel(&bytes, 28, 24));
+		assert_eq!(automap.wall_colour.tuple(), pixel(&bytes, 30, 24));
+
+		// while behind the camera nothing has been seen, neither cells nor the wall along the west
+		// edge of the scene
+		assert_eq!((0, 0, 0, 0), pixel(&bytes, 16, 24));
+		assert_eq!((0, 0, 0, 0), pixel(&bytes, 14, 24));
+	}
+
+	#[test]
+	fn test_player_up_turns_with_camera() {
+		let scene = load_scene();
+
+		// looking south at the wall across the room, which is 96 units ahead
+		let camera = Camera::new(160, 160, trig::ANGLE_90, 20);
+		let mut automap = Automap::new(4, Orientation::NorthUp, Placement::FullScreen).unwrap();
+		automap.background = None;
+
+		// drawn below the camera with north up
+		let bytes = render_map(&automap, &scene, &camera);
+		assert_eq!(automap.wall_colour.tuple(), pixel(&bytes, 24, 30));
+		assert_eq!((0, 0, 0, 0), pixel(&bytes, 24, 18));
+
+		// and above it when the map turns so the way the camera faces is up
+		automap.set_orientation(Orientation::PlayerUp);
+		let bytes = render_map(&automap, &scene, &camera);
+		assert_eq!(automap.wall_colour.tuple(), pixel(&bytes, 24, 18));
+		assert_eq!((0, 0, 0, 0), pixel(&bytes, 24, 30));
+	}
+
+	#[test]
+	fn test_inset() {
+		let scene  = load_scene();
+		let camera = Camera::new(160, 160, trig::ANGLE_0, 20);
+
+		// a 16x12 map two pixels in from the top right corner, drawn over an opaque background
+		let placement = Placement::Inset { corner: Corner::TopRight, width: 16, height: 12, margin: 2 };
+		let area      = placement.area(48, 48);
+		assert_eq!((30, 2, 16, 12), (area.x, area.y, area.width, area.height));
+
+		let mut automap = Automap::new(4, Orientation::NorthUp, placement).unwrap();
+		automap.background = Some(Colour::new(0, 0, 0xFF, 0xFF));
+		let bytes = render_map(&automap, &scene, &camera);
+
+		assert_eq!((0, 0, 0xFF, 0xFF), pixel(&bytes, 30, 2));
+		assert_eq!((0, 0, 0xFF, 0xFF), pixel(&bytes, 45, 13));
+		assert_eq!((0, 0, 0, 0), pixel(&bytes, 29, 2));
+		assert_eq!((0, 0, 0, 0), pixel(&bytes, 46, 13));
+		assert_eq!((0, 0, 0, 0), pixel(&bytes, 30, 14));
+
+		// centred on the camera, with the cell ahead and its far wall clipped to the inset
+		assert_eq!(automap.player_colour.tuple(), pixel(&bytes, 38, 8));
+		assert_eq!(automap.floor_colour.tuple(), pixel(&bytes, 42, 8));
+		assert_eq!(automap.wall_colour.tuple(), pixel(&bytes, 44, 8));
+	}
+
+	#[test]
+	fn test_map_view() {
+		let automap = Automap::new(8, Orientation::PlayerUp, Placement::FullScreen).unwrap();
+		let camera  = Camera::new(160, 96, trig::ANGLE_90, 20);
+		let view    = MapView::new(&automap, Rect::new(0, 0, 64, 48), &camera);
+
+		// facing south, the camera is in the middle with what lies south of it up the screen and
+		// what lies west of it to the right
+		assert_eq!((32, 24), view.to_screen(160, 96));
+		assert_eq!((32, 16), view.to_screen(160, 160));
+		assert_eq!((40, 24), view.to_screen(96, 96));
+
+		// and converting back lands within a pixel's width of the world point
+		for (x, y) in [(160, 160), (96, 96), (200, 40), (131, 77)] {
+			let (screen_x, screen_y) = view.to_screen(x, y);
+			let (world_x, world_y)   = view.to_world(screen_x, screen_y);
+			assert!((world_x - x).abs() <= 8 && (world_y - y).abs() <= 8, "{:?}", (world_x, world_y));
+		}
+	}
+}
@@ -6,15 +6,13 @@ pub struct Camera {
 	y: i32,
 	angle: i32,
 	horizon: i32,
+	height: i32,
+	fov: i32,
 }
 
 impl Camera {
 	pub fn new(x: i32, y: i32, angle: i32, horizon: i32) -> Camera {
-		Camera { x, y, angle, horizon }
-	}
-
-	pub fn default() -> Camera {
-		Camera::new(0, 0, 0, consts::PROJECTION_PLANE_HORIZON)
+		Camera { x, y, angle, horizon, height: consts::PLAYER_HEIGHT, fov: trig::ANGLE_60 }
 	}
 
 	pub fn rotate(&mut self, angle: i32) {
@@ -23,10 +21,9 @@ impl Camera {
 		while self.angle < trig::ANGLE_0    { self.angle += trig::ANGLE_360; }
 	}
 
+	// moves the horizon up or down the display, the renderer keeps it on screen
 	pub fn pitch(&mut self, distance: i32) {
 		self.horizon += distance;
-		if self.horizon < 20  { self.horizon =  20; }
-		if self.horizon > 180 { self.horizon = 180; }
 	}
 
 	pub fn move_to(&mut self, x: i32, y: i32) {
@@ -57,6 +54,33 @@ impl Camera {
 	pub fn horizon(&self) -> i32 {
 		self.horizon
 	}
+
+	pub fn set_horizon(&mut self, horizon: i32) {
+		self.horizon = horizon;
+	}
+
+	// height of the eye above the floor, which stays between the floor and the ceiling
+	pub fn height(&self) -> i32 {
+		self.height
+	}
+
+	pub fn set_height(&mut self, height: i32) {
+		self.height = height.clamp(1, consts::WALL_HEIGHT - 1);
+	}
+
+	pub fn fov(&self) -> i32 {
+		self.fov
+	}
+
+	pub fn set_fov(&mut self, fov: i32) {
+		self.fov = fov.clamp(trig::ANGLE_5, trig::ANGLE_180 - trig::ANGLE_5);
+	}
+}
+
+impl Default for Camera {
+	fn default() -> Camera {
+		Camera::new(0, 0, 0, consts::PROJECTION_PLANE_HORIZON)
+	}
 }
 
 impl TryFrom<&serde_json::Value> for Camera {
@@ -66,7 +90,43 @@ impl TryFrom<&serde_json::Value> for Camera {
 		let x = json["x"].as_i64().unwrap() as i32;
 		let y = json["y"].as_i64().unwrap() as i32;
 		let a = json["angle"].as_i64().unwrap() as i32;
-		let h = json["horizon"].as_i64().unwrap() as i32;
-		Ok(Camera::new(x, y, a, h))
+
+		// angle is given in degrees, like the field of view, rather than the units of the trig tables
+		let mut camera = Camera::new(x, y, (a * trig::ANGLE_60 / 60).rem_euclid(trig::ANGLE_360), consts::PROJECTION_PLANE_HORIZON);
+
+		// horizon is optional, levels usually leave it to the renderer's configuration
+		if let Some(horizon) = json["horizon"].as_i64() {
+			camera.set_horizon(horizon as i32);
+		}
+
+		// eye height is optional and defaults to that of a standing player
+		if let Some(height) = json["height"].as_i64() {
+			camera.set_height(height as i32);
+		}
+
+		// field of view is optional and given in degrees
+		if let Some(fov) = json["fov"].as_i64() {
+			camera.set_fov(fov as i32 * trig::ANGLE_60 / 60);
+		}
+
+		Ok(camera)
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_angle_in_degrees() {
+		let json   = serde_json::json!({ "x": 96, "y": 160, "angle": 90, "fov": 90 });
+		let camera = Camera::try_from(&json).unwrap();
+		assert_eq!(trig::ANGLE_90, camera.angle());
+		assert_eq!(trig::ANGLE_90, camera.fov());
+
+		// turning the other way comes round to the same angle as turning all the way
+		let json   = serde_json::json!({ "x": 96, "y": 160, "angle": -90 });
+		let camera = Camera::try_from(&json).unwrap();
+		assert_eq!(trig::ANGLE_270, camera.angle());
+	}
+}
@@ -0,0 +1,96 @@
+use crate::render::{ Colour, FaceShading, Fog, Palette };
+use serde_json;
+use shared::consts;
+
+pub struct RenderConfig {
+	pub width: i32,
+	pub height: i32,
+	pub horizon: i32,   // row the horizon rests on when looking straight ahead
+	pub fog: Option<Fog>,
+	pub face_shading: Option<FaceShading>,
+	pub ceiling_colour: Colour,
+	pub floor_colour: Colour,
+	pub mipmaps: bool,
+	pub palettes: Vec<Palette>, // drawn in true colour when empty, the first is the normal palette
+	pub picking: bool,          // record what is drawn at each pixel, see Renderer::pick
+}
+
+impl RenderConfig {
+	pub fn new(width: i32, height: i32, horizon: i32) -> Result<RenderConfig, &'static str> {
+		if width <= 0 || height <= 0 {
+			return Err("Width and height must be positive values");
+		}
+
+		if horizon < 0 || horizon >= height {
+			return Err("Horizon must lie within the height of the display");
+		}
+
+		Ok(RenderConfig { width, height, horizon, ..RenderConfig::default() })
+	}
+}
+
+impl Default for RenderConfig {
+	fn default() -> RenderConfig {
+		RenderConfig {
+			width: consts::PROJECTION_PLANE_WIDTH,
+			height: consts::PROJECTION_PLANE_HEIGHT,
+			horizon: consts::PROJECTION_PLANE_HORIZON,
+			fog: None,
+			face_shading: None,
+			ceiling_colour: Colour::new(0x38, 0x38, 0x38, 0xFF),
+			floor_colour: Colour::new(0x70, 0x70, 0x70, 0xFF),
+			mipmaps: false,
+			palettes: vec![],
+			picking: false,
+		}
+	}
+}
+
+impl TryFrom<&serde_json::Value> for RenderConfig {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		// every setting is optional, missing values fall back to the defaults
+		let default = RenderConfig::default();
+		let width   = json["width"].as_i64().map_or(default.width, |width| width as i32);
+		let height  = json["height"].as_i64().map_or(default.height, |height| height as i32);
+		let horizon = json["horizon"].as_i64().map_or(height >> 1, |horizon| horizon as i32);
+		let mut config = RenderConfig::new(width, height, horizon)?;
+
+		if json["fog"].is_object() {
+			config.fog = Some(Fog::try_from(&json["fog"])?);
+		}
+
+		if json["face_shading"].is_object() {
+			config.face_shading = Some(FaceShading::try_from(&json["face_shading"])?);
+		}
+
+		// flat colours drawn wherever there is no floor or ceiling texture
+		if !json["ceiling_colour"].is_null() {
+			config.ceiling_colour = Colour::try_from(&json["ceiling_colour"])?;
+		}
+
+		if !json["floor_colour"].is_null() {
+			config.floor_colour = Colour::try_from(&json["floor_colour"])?;
+		}
+
+		// distant surfaces alias heavily without mipmaps, but that is the look we want by default
+		config.mipmaps = json["mipmaps"].as_bool().unwrap_or(false);
+
+		config.picking = json["picking"].as_bool().unwrap_or(false);
+
+		// drawing with a palette, each flash adds a copy of it tinted towards a colour
+		if json["palette"].is_object() {
+			let palette = Palette::try_from(&json["palette"]["colours"])?;
+			let flashes = json["palette"]["flashes"].as_array().map_or(&[][..], |flashes| &flashes[..]).iter().map(|flash| {
+				let colour = Colour::try_from(&flash["colour"])?;
+				let amount = flash["amount"].as_u64().ok_or("Palette flash must have an amount")?.min(255) as u8;
+				Ok(palette.tint(colour, amount))
+			}).collect::<Result<Vec<Palette>, &'static str>>()?;
+
+			config.palettes = std::iter::once(palette).chain(flashes).collect();
+		}
+
+		Ok(config)
+	}
+}
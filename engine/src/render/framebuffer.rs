@@ -0,0 +1,252 @@
+use crate::render::{ Colour, Indexed, Palette };
+
+// somewhere the renderer can draw the display, one pixel at a time
+pub trait FrameBuffer {
+	fn width(&self) -> i32;
+	fn height(&self) -> i32;
+	fn get(&self, x: i32, y: i32) -> Colour;
+	fn set(&mut self, x: i32, y: i32, colour: Colour);
+
+	// draw a colour over the pixel already there, respecting its alpha
+	fn blend(&mut self, x: i32, y: i32, colour: Colour) {
+		let blended = colour.blend(&self.get(x, y));
+		self.set(x, y, blended);
+	}
+
+	// draw a palette index, which buffers of colours show as its colour in the palette
+	fn set_indexed(&mut self, x: i32, y: i32, index: Indexed, palette: &Palette) {
+		self.set(x, y, palette.colour(index));
+	}
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum PixelFormat {
+	Rgba8,
+	Bgra8,
+	Rgb565, // stored little endian, with no alpha
+}
+
+impl PixelFormat {
+	pub fn bytes_per_pixel(&self) -> usize {
+		match self {
+			PixelFormat::Rgba8 | PixelFormat::Bgra8 => 4,
+			PixelFormat::Rgb565                     => 2,
+		}
+	}
+}
+
+// frame buffer over a slice of bytes, where each row starts pitch bytes after the one before
+pub struct ByteBuffer<'a> {
+	bytes: &'a mut [u8],
+	width: i32,
+	height: i32,
+	pitch: usize,
+	format: PixelFormat,
+}
+
+impl ByteBuffer<'_> {
+	pub fn new(bytes: &mut [u8], width: i32, height: i32, pitch: usize, format: PixelFormat) -> Result<ByteBuffer<'_>, &'static str> {
+		if width <= 0 || height <= 0 {
+			return Err("Width and height must be positive values");
+		}
+
+		if pitch < width as usize * format.bytes_per_pixel() {
+			return Err("Pitch must be wide enough to hold a row of pixels");
+		}
+
+		if bytes.len() < pitch * (height as usize - 1) + width as usize * format.bytes_per_pixel() {
+			return Err("Buffer is too small to hold every row of pixels");
+		}
+
+		Ok(ByteBuffer { bytes, width, height, pitch, format })
+	}
+
+	fn idx(&self, x: i32, y: i32) -> usize {
+		y as usize * self.pitch + x as usize * self.format.bytes_per_pixel()
+	}
+}
+
+impl FrameBuffer for ByteBuffer<'_> {
+	fn width(&self) -> i32 {
+		self.width
+	}
+
+	fn height(&self) -> i32 {
+		self.height
+	}
+
+	fn get(&self, x: i32, y: i32) -> Colour {
+		let idx = self.idx(x, y);
+		let px  = &self.bytes[idx..idx + self.format.bytes_per_pixel()];
+
+		match self.format {
+			PixelFormat::Rgba8  => Colour::new(px[0], px[1], px[2], px[3]),
+			PixelFormat::Bgra8  => Colour::new(px[2], px[1], px[0], px[3]),
+			PixelFormat::Rgb565 => {
+				let packed = u16::from_le_bytes([px[0], px[1]]);
+				let r = ((packed >> 11) & 0x1F) as u8;
+				let g = ((packed >> 5) & 0x3F) as u8;
+				let b = (packed & 0x1F) as u8;
+				Colour::new((r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2), 0xFF)
+			},
+		}
+	}
+
+	fn set(&mut self, x: i32, y: i32, colour: Colour) {
+		let idx = self.idx(x, y);
+		let px  = &mut self.bytes[idx..idx + self.format.bytes_per_pixel()];
+
+		match self.format {
+			PixelFormat::Rgba8  => px.copy_from_slice(&[colour.r, colour.g, colour.b, colour.a]),
+			PixelFormat::Bgra8  => px.copy_from_slice(&[colour.b, colour.g, colour.r, colour.a]),
+			PixelFormat::Rgb565 => {
+				let packed = ((colour.r as u16 >> 3) << 11) | ((colour.g as u16 >> 2) << 5) | (colour.b as u16 >> 3);
+				px.copy_from_slice(&packed.to_le_bytes());
+			},
+		}
+	}
+}
+
+// frame buffer of packed 0xAARRGGBB pixels, where each row starts pitch pixels after the one before
+pub struct ArgbBuffer<'a> {
+	pixels: &'a mut [u32],
+	width: i32,
+	height: i32,
+	pitch: usize,
+}
+
+impl ArgbBuffer<'_> {
+	pub fn new(pixels: &mut [u32], width: i32, height: i32, pitch: usize) -> Result<ArgbBuffer<'_>, &'static str> {
+		if width <= 0 || height <= 0 {
+			return Err("Width and height must be positive values");
+		}
+
+		if pitch < width as usize {
+			return Err("Pitch must be wide enough to hold a row of pixels");
+		}
+
+		if pixels.len() < pitch * (height as usize - 1) + width as usize {
+			return Err("Buffer is too small to hold every row of pixels");
+		}
+
+		Ok(ArgbBuffer { pixels, width, height, pitch })
+	}
+}
+
+impl FrameBuffer for ArgbBuffer<'_> {
+	fn width(&self) -> i32 {
+		self.width
+	}
+
+	fn height(&self) -> i32 {
+		self.height
+	}
+
+	fn get(&self, x: i32, y: i32) -> Colour {
+		let [a, r, g, b] = self.pixels[y as usize * self.pitch + x as usize].to_be_bytes();
+		Colour::new(r, g, b, a)
+	}
+
+	fn set(&mut self, x: i32, y: i32, colour: Colour) {
+		self.pixels[y as usize * self.pitch + x as usize] = u32::from_be_bytes([colour.a, colour.r, colour.g, colour.b]);
+	}
+}
+
+// frame buffer of palette indices, one byte a pixel, where each row starts pitch bytes after the
+// one before. The renderer draws indices straight in when it has palettes, leaving them to be
+// turned into colours when shown, while colours drawn over the top, such as an overlay, are
+// stored as the index of the nearest colour in the palette
+pub struct IndexedBuffer<'a> {
+	indices: &'a mut [u8],
+	width: i32,
+	height: i32,
+	pitch: usize,
+	palette: &'a Palette,
+}
+
+impl IndexedBuffer<'_> {
+	pub fn new<'a>(indices: &'a mut [u8], width: i32, height: i32, pitch: usize, palette: &'a Palette) -> Result<IndexedBuffer<'a>, &'static str> {
+		if width <= 0 || height <= 0 {
+			return Err("Width and height must be positive values");
+		}
+
+		if pitch < width as usize {
+			return Err("Pitch must be wide enough to hold a row of pixels");
+		}
+
+		if indices.len() < pitch * (height as usize - 1) + width as usize {
+			return Err("Buffer is too small to hold every row of pixels");
+		}
+
+		Ok(IndexedBuffer { indices, width, height, pitch, palette })
+	}
+}
+
+impl FrameBuffer for IndexedBuffer<'_> {
+	fn width(&self) -> i32 {
+		self.width
+	}
+
+	fn height(&self) -> i32 {
+		self.height
+	}
+
+	fn get(&self, x: i32, y: i32) -> Colour {
+		self.palette.colour(Indexed(self.indices[y as usize * self.pitch + x as usize]))
+	}
+
+	fn set(&mut self, x: i32, y: i32, colour: Colour) {
+		self.indices[y as usize * self.pitch + x as usize] = self.palette.nearest(colour).0;
+	}
+
+	fn set_indexed(&mut self, x: i32, y: i32, index: Indexed, _palette: &Palette) {
+		self.indices[y as usize * self.pitch + x as usize] = index.0;
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_pixel_formats() {
+		let colour = Colour::new(0xF8, 0xFC, 0x08, 0xFF);
+
+		// channels which survive being packed into five or six bits
+		for format in [PixelFormat::Rgba8, PixelFormat::Bgra8, PixelFormat::Rgb565] {
+			let mut bytes = vec![0; 64];
+			let mut buf   = ByteBuffer::new(&mut bytes, 2, 2, 20, format).unwrap();
+			buf.set(1, 1, Colour::new(0xFF, 0x00, 0xFF, 0xFF));
+			assert_eq!((0xFF, 0x00, 0xFF, 0xFF), buf.get(1, 1).tuple());
+		}
+
+		let mut bytes = vec![0; 8];
+		ByteBuffer::new(&mut bytes, 2, 1, 8, PixelFormat::Bgra8).unwrap().set(1, 0, colour);
+		assert_eq!([0, 0, 0, 0, 0x08, 0xFC, 0xF8, 0xFF], bytes[..]);
+
+		let mut pixels = vec![0; 6];
+		ArgbBuffer::new(&mut pixels, 2, 2, 3).unwrap().set(1, 1, colour);
+		assert_eq!(0xFFF8FC08, pixels[4]);
+	}
+
+	#[test]
+	fn test_buffer_too_small() {
+		let mut bytes = vec![0; 20];
+		assert!(ByteBuffer::new(&mut bytes, 2, 2, 16, PixelFormat::Rgba8).is_err());
+		assert!(ByteBuffer::new(&mut bytes, 4, 2, 8, PixelFormat::Rgba8).is_err());
+		assert!(ByteBuffer::new(&mut bytes, 2, 2, 16, PixelFormat::Rgb565).is_ok());
+	}
+
+	#[test]
+	fn test_indexed_buffer() {
+		let palette     = Palette::new(vec![Colour::new(0, 0, 0, 0xFF), Colour::new(0xFF, 0, 0, 0xFF)]).unwrap();
+		let mut indices = vec![0; 6];
+		let mut buf     = IndexedBuffer::new(&mut indices, 2, 2, 3, &palette).unwrap();
+
+		// colours are stored as the nearest in the palette, and indices as they are
+		buf.set(1, 0, Colour::new(0xF0, 0x10, 0, 0xFF));
+		buf.set_indexed(1, 1, Indexed(7), &palette);
+		assert_eq!((0xFF, 0, 0, 0xFF), buf.get(1, 0).tuple());
+		assert_eq!([0, 1, 0, 0, 7, 0], indices[..]);
+	}
+}
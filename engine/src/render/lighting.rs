@@ -0,0 +1,266 @@
+use crate::render::{ Colour, Face };
+use crate::trig;
+use serde_json;
+use shared::consts;
+use shared::fp::{ ToFixedPoint, FromFixedPoint };
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Falloff {
+	Linear,
+	Quadratic,
+	Exponential,
+}
+
+impl TryFrom<&serde_json::Value> for Falloff {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		match json.as_str() {
+			None | Some("linear") => Ok(Falloff::Linear),
+			Some("quadratic")     => Ok(Falloff::Quadratic),
+			Some("exponential")   => Ok(Falloff::Exponential),
+			Some(_)               => Err("Fog falloff must be one of linear, quadratic or exponential"),
+		}
+	}
+}
+
+#[derive(Copy, Clone)]
+pub struct Fog {
+	pub colour: Colour,
+	pub start: i32,
+	pub end: i32,
+	pub falloff: Falloff,
+}
+
+impl Fog {
+	pub fn new(colour: Colour, start: i32, end: i32, falloff: Falloff) -> Result<Fog, &'static str> {
+		if start < 0 || end <= start {
+			return Err("Fog must start at a positive distance and end beyond its start");
+		}
+
+		Ok(Fog { colour, start, end, falloff })
+	}
+
+	// amount of fog covering a surface at the given distance, from 0.0 (none) to 1.0 (opaque)
+	fn density(&self, distance: i32) -> f64 {
+		let t = ((distance - self.start) as f64 / (self.end - self.start) as f64).clamp(0.0, 1.0);
+
+		match self.falloff {
+			Falloff::Linear      => t,
+			Falloff::Quadratic   => t * t,
+			Falloff::Exponential => (1.0 - (-4.0 * t).exp()) / (1.0 - (-4.0f64).exp()),
+		}
+	}
+}
+
+impl TryFrom<&serde_json::Value> for Fog {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		let colour  = Colour::try_from(&json["colour"])?;
+		let start   = json["start"].as_i64().ok_or("Fog must have a start distance")? as i32;
+		let end     = json["end"].as_i64().ok_or("Fog must have an end distance")? as i32;
+		let falloff = Falloff::try_from(&json["falloff"])?;
+		Fog::new(colour, start, end, falloff)
+	}
+}
+
+// walls darkened by the way they face, so that corners are easier to make out. Floors and
+// ceilings are left as they are
+#[derive(Copy, Clone)]
+pub enum FaceShading {
+	Sides(u8),                                   // walls facing east or west are darkened by amount / 255
+	Directional { direction: i32, ambient: u8 }, // lit by light shining in a direction, walls facing away keep only the ambient level
+}
+
+impl FaceShading {
+	pub fn directional(direction: i32, ambient: u8) -> FaceShading {
+		FaceShading::Directional { direction: direction.rem_euclid(trig::ANGLE_360), ambient }
+	}
+
+	// brightness of a face, from 0 (black) to 255 (as lit as its tile)
+	pub fn level(&self, face: Face) -> u8 {
+		match (self, face) {
+			(_, Face::Up | Face::Down)                            => 255,
+			(FaceShading::Sides(amount), Face::East | Face::West) => 255 - amount,
+			(FaceShading::Sides(_), _)                            => 255,
+			(FaceShading::Directional { direction, ambient }, _)  => {
+				// the more directly a face looks back along the light the brighter it is
+				let (x, y) = face.normal();
+				let facing = -(x * trig::cos(*direction) + y * trig::sin(*direction));
+				let amount = (255 - *ambient as i32) * facing.clamp(0, 1.to_fp()) / 1.to_fp();
+				(*ambient as i32 + amount) as u8
+			},
+		}
+	}
+
+	pub fn apply(&self, light: u8, face: Face) -> u8 {
+		(light as u32 * self.level(face) as u32 / 255) as u8
+	}
+}
+
+impl TryFrom<&serde_json::Value> for FaceShading {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		// a direction in degrees, measured like the camera's angle, lights walls by the way they
+		// face, otherwise east and west facing walls are darkened by a fixed amount
+		if let Some(direction) = json["direction"].as_i64() {
+			let ambient = json["ambient"].as_u64().map_or(128, |ambient| ambient.min(255) as u8);
+			return Ok(FaceShading::directional(direction as i32 * trig::ANGLE_60 / 60, ambient));
+		}
+
+		let amount = json["darken"].as_u64().ok_or("Face shading must have a direction or an amount to darken by")?;
+		Ok(FaceShading::Sides(amount.min(255) as u8))
+	}
+}
+
+// how much a surface is darkened by the light level of its tile and covered by fog, and the
+// colour of any point lights brightening it
+#[derive(Copy, Clone)]
+pub struct Shade {
+	pub light: u8,
+	pub fog: u8,
+	pub glow: Colour,
+}
+
+impl Shade {
+	pub fn glow(mut self, glow: Colour) -> Shade {
+		self.glow = glow;
+		self
+	}
+
+	pub fn is_glowing(&self) -> bool {
+		self.glow.r > 0 || self.glow.g > 0 || self.glow.b > 0
+	}
+}
+
+// shades surfaces according to how brightly they are lit and how far they are from the camera
+pub struct Lighting {
+	fog: Colour,
+	fog_table: Vec<u8>,
+}
+
+impl Lighting {
+	pub fn new(fog: Option<&Fog>) -> Lighting {
+		match fog {
+			Some(fog) => {
+				let fog_table = (0..=consts::MAX_RAY_LENGTH).map(|distance| (fog.density(distance) * 255.0).round() as u8).collect();
+				Lighting { fog: fog.colour, fog_table }
+			},
+			None => Lighting { fog: Colour::new(0, 0, 0, 0), fog_table: vec![] },
+		}
+	}
+
+	// amount of fog, from 0 to 255, at a fixed point distance from the camera
+	pub fn fog_amount(&self, distance: i32) -> u8 {
+		if self.fog_table.is_empty() { return 0; }
+		self.fog_table[distance.to_i32().clamp(0, consts::MAX_RAY_LENGTH) as usize]
+	}
+
+	pub fn shade_at(&self, distance: i32, light: u8) -> Shade {
+		Shade { light, fog: self.fog_amount(distance), glow: Colour::new(0, 0, 0, 0) }
+	}
+
+	pub fn apply(&self, colour: Colour, shade: Shade) -> Colour {
+		// darken first so fog keeps its own colour in unlit areas. Point lights add to the light
+		// level of each channel, but never brighten a surface beyond its own colour
+		let colour = if shade.is_glowing() {
+			let lit = |c: u8, glow: u8| (c as u32 * (shade.light as u32 + glow as u32).min(255) / 255) as u8;
+			Colour::new(lit(colour.r, shade.glow.r), lit(colour.g, shade.glow.g), lit(colour.b, shade.glow.b), colour.a)
+		} else if shade.light < 255 {
+			colour.lerp(&Colour::new(0, 0, 0, 0), 255 - shade.light)
+		} else {
+			colour
+		};
+		if shade.fog == 0 { return colour; }
+		colour.lerp(&self.fog, shade.fog)
+	}
+
+	pub fn shade(&self, colour: Colour, distance: i32, light: u8) -> Colour {
+		self.apply(colour, self.shade_at(distance, light))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use shared::fp::ToFixedPoint;
+
+	#[test]
+	fn test_fog_amount_between_start_and_end() {
+		for falloff in [Falloff::Linear, Falloff::Quadratic, Falloff::Exponential] {
+			let fog      = Fog::new(Colour::new(0, 0, 0, 255), 64, 512, falloff).unwrap();
+			let lighting = Lighting::new(Some(&fog));
+
+			assert_eq!(0, lighting.fog_amount(32.to_fp()));
+			assert_eq!(0, lighting.fog_amount(64.to_fp()));
+			assert!(lighting.fog_amount(128.to_fp()) < lighting.fog_amount(256.to_fp()));
+			assert_eq!(255, lighting.fog_amount(512.to_fp()));
+			assert_eq!(255, lighting.fog_amount(4096.to_fp()));
+		}
+	}
+
+	#[test]
+	fn test_no_fog() {
+		let lighting = Lighting::new(None);
+		let colour   = lighting.shade(Colour::new(10, 20, 30, 255), 1024.to_fp(), 255);
+		assert_eq!((10, 20, 30, 255), colour.tuple());
+	}
+
+	#[test]
+	fn test_light_level() {
+		let lighting = Lighting::new(None);
+		assert_eq!((0, 0, 0, 255), lighting.shade(Colour::new(200, 100, 50, 255), 0, 0).tuple());
+		assert_eq!((101, 51, 26, 255), lighting.shade(Colour::new(200, 100, 50, 255), 0, 128).tuple());
+	}
+
+	#[test]
+	fn test_point_lights() {
+		use crate::scene::{ PointLight, Scene, TextureTile, Tile };
+
+		// two cells side by side with a wall between them
+		let x_walls = vec![Tile::Empty, Tile::Surface(TextureTile::new(0))];
+		let mut scene = Scene::new(2, 1, vec![Tile::Empty, Tile::Empty], x_walls, vec![Tile::Empty, Tile::Empty], vec![Tile::Empty, Tile::Empty]).unwrap();
+
+		let mut light = PointLight::new(32, 32, 128, Colour::new(255, 0, 0, 255), 255);
+		scene.add_point_light(light);
+		assert!(scene.glow_at(96, 32).r > 0);
+
+		light.shadows = true;
+		scene.clear_point_lights();
+		scene.add_point_light(light);
+		assert_eq!(255, scene.glow_at(32, 32).r);
+		assert_eq!(0, scene.glow_at(96, 32).r);
+
+		// floors and ceilings take whether a light reaches them from the cell they are in
+		let lit = scene.lit_cells();
+		assert_eq!(255, scene.surface_glow_at(&lit, 32, 32).r);
+		assert_eq!(0, scene.surface_glow_at(&lit, 96, 32).r);
+		assert_eq!(0, scene.surface_glow_at(&lit, 70, 60).r);
+
+		// the light brightens its own channels of a dimly lit surface up to their full colour
+		let lighting = Lighting::new(None);
+		let shade    = lighting.shade_at(0, 128).glow(scene.glow_at(32, 32));
+		assert_eq!((200, 50, 25, 255), lighting.apply(Colour::new(200, 100, 50, 255), shade).tuple());
+	}
+
+	#[test]
+	fn test_face_shading() {
+		let sides = FaceShading::Sides(64);
+		assert_eq!(255, sides.level(Face::North));
+		assert_eq!(191, sides.level(Face::West));
+		assert_eq!(95, sides.apply(128, Face::East));
+
+		// light shining straight down the scene, towards the south
+		let directional = FaceShading::directional(trig::ANGLE_90, 100);
+		assert_eq!(255, directional.level(Face::North));
+		assert_eq!(100, directional.level(Face::South));
+		assert_eq!(100, directional.level(Face::East));
+		assert_eq!(255, directional.level(Face::Up));
+
+		// levels give the direction in degrees
+		let json = serde_json::json!({ "direction": 90, "ambient": 100 });
+		assert!(matches!(FaceShading::try_from(&json), Ok(FaceShading::Directional { direction: trig::ANGLE_90, ambient: 100 })));
+	}
+}
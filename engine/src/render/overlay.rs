@@ -0,0 +1,226 @@
+use crate::render::{ Colour, FrameBuffer, TextureMap };
+use crate::scene::texture_id;
+use serde_json;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Rect {
+	pub x: i32,
+	pub y: i32,
+	pub width: i32,
+	pub height: i32,
+}
+
+impl Rect {
+	pub fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+		Rect { x, y, width, height }
+	}
+
+	// the area covered by both rectangles, which is empty if they do not overlap
+	pub fn intersect(&self, other: &Rect) -> Rect {
+		let x      = std::cmp::max(self.x, other.x);
+		let y      = std::cmp::max(self.y, other.y);
+		let right  = std::cmp::min(self.x + self.width, other.x + other.width);
+		let bottom = std::cmp::min(self.y + self.height, other.y + other.height);
+		Rect::new(x, y, (right - x).max(0), (bottom - y).max(0))
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.width <= 0 || self.height <= 0
+	}
+
+	pub fn contains(&self, x: i32, y: i32) -> bool {
+		x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+	}
+}
+
+// a font drawn from a sheet of equally sized glyphs within a texture, laid out left to right
+// then top to bottom in character order
+pub struct BitmapFont {
+	texture: u32,
+	glyph_width: i32,
+	glyph_height: i32,
+	first: u32,
+	count: u32,
+}
+
+impl BitmapFont {
+	pub fn new(texture: u32, glyph_width: i32, glyph_height: i32, first: char, count: u32) -> Result<BitmapFont, &'static str> {
+		if glyph_width <= 0 || glyph_height <= 0 {
+			return Err("Glyph width and height must be positive values");
+		}
+
+		Ok(BitmapFont { texture, glyph_width, glyph_height, first: first as u32, count })
+	}
+
+	pub fn glyph_width(&self) -> i32 {
+		self.glyph_width
+	}
+
+	pub fn glyph_height(&self) -> i32 {
+		self.glyph_height
+	}
+
+	// where the glyph for a character lies in the sheet, if the font has one
+	fn glyph(&self, c: char, texture_width: i32) -> Option<Rect> {
+		let index = (c as u32).checked_sub(self.first).filter(|index| *index < self.count)? as i32;
+		let per_row = std::cmp::max(1, texture_width / self.glyph_width);
+		Some(Rect::new(index % per_row * self.glyph_width, index / per_row * self.glyph_height, self.glyph_width, self.glyph_height))
+	}
+}
+
+impl TryFrom<&serde_json::Value> for BitmapFont {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		// the sheet holds the printable ASCII characters unless told otherwise
+		let texture = texture_id(&json["texture"]).ok_or("Font texture ids start at 1")?;
+		let width   = json["glyph_width"].as_i64().ok_or("Font must have a glyph width")? as i32;
+		let height  = json["glyph_height"].as_i64().ok_or("Font must have a glyph height")? as i32;
+		let first   = json["first"].as_str().map_or(Some(' '), |first| first.chars().next()).ok_or("Font first character must not be empty")?;
+		let count   = json["count"].as_u64().map_or(95, |count| count as u32);
+		BitmapFont::new(texture, width, height, first, count)
+	}
+}
+
+// draws rectangles, images and text over a frame buffer, such as a HUD drawn after the scene has
+// been rendered. Images come from a texture map, either the renderer's or a separate atlas, and
+// everything is blended over what is already there and clipped to the clip rectangle
+pub struct Overlay<'a, B: FrameBuffer> {
+	buf: &'a mut B,
+	clip: Rect,
+}
+
+impl<'a, B: FrameBuffer> Overlay<'a, B> {
+	pub fn new(buf: &'a mut B) -> Overlay<'a, B> {
+		let clip = Rect::new(0, 0, buf.width(), buf.height());
+		Overlay { buf, clip }
+	}
+
+	pub fn clip(&self) -> Rect {
+		self.clip
+	}
+
+	// restricts drawing to part of the display, which is always kept within the frame buffer
+	pub fn set_clip(&mut self, clip: Rect) {
+		self.clip = clip.intersect(&Rect::new(0, 0, self.buf.width(), self.buf.height()));
+	}
+
+	pub fn fill(&mut self, rect: Rect, colour: Colour) {
+		let area = rect.intersect(&self.clip);
+		for x in area.x..area.x + area.width {
+			for y in area.y..area.y + area.height {
+				self.buf.blend(x, y, colour);
+			}
+		}
+	}
+
+	pub fn plot(&mut self, x: i32, y: i32, colour: Colour) {
+		if self.clip.contains(x, y) { self.buf.blend(x, y, colour); }
+	}
+
+	// draws a line a pixel wide between two points, including both ends
+	pub fn line(&mut self, (x0, y0): (i32, i32), (x1, y1): (i32, i32), colour: Colour) {
+		let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+		let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+		let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+
+		loop {
+			self.plot(x, y, colour);
+			if x == x1 && y == y1 { break; }
+
+			let e2 = 2 * error;
+			if e2 >= dy { error += dy; x += sx; }
+			if e2 <= dx { error += dx; y += sy; }
+		}
+	}
+
+	// draws the src area of a texture with its top left corner at x, y
+	pub fn blit(&mut self, textures: &TextureMap, code: u32, src: Rect, x: i32, y: i32) {
+		self.draw_texels(textures, code, src, Rect::new(x, y, src.width, src.height), None);
+	}
+
+	// draws the src area of a texture stretched or shrunk to cover the dst area
+	pub fn blit_scaled(&mut self, textures: &TextureMap, code: u32, src: Rect, dst: Rect) {
+		self.draw_texels(textures, code, src, dst, None);
+	}
+
+	// draws text in a colour with its top left corner at x, y, where the alpha of each glyph's
+	// texels says how much of the colour covers the display. Each line starts below the last,
+	// and characters the font lacks leave a space
+	pub fn text(&mut self, textures: &TextureMap, font: &BitmapFont, text: &str, x: i32, y: i32, colour: Colour) {
+		for (row, line) in text.lines().enumerate() {
+			let line_y = y + row as i32 * font.glyph_height;
+			for (index, c) in line.chars().enumerate() {
+				if let Some(glyph) = font.glyph(c, textures.texture_width() as i32) {
+					self.draw_texels(textures, font.texture, glyph, Rect::new(x + index as i32 * font.glyph_width, line_y, glyph.width, glyph.height), Some(colour));
+				}
+			}
+		}
+	}
+
+	fn draw_texels(&mut self, textures: &TextureMap, code: u32, src: Rect, dst: Rect, tint: Option<Colour>) {
+		if src.is_empty() { return; }
+
+		// only the part of the texture that exists and lands within the clip rectangle is drawn
+		let texture = Rect::new(0, 0, textures.texture_width() as i32, textures.texture_height() as i32);
+		let area    = dst.intersect(&self.clip);
+
+		for dst_x in area.x..area.x + area.width {
+			let tex_x = src.x + (dst_x - dst.x) * src.width / dst.width;
+			if tex_x < texture.x || tex_x >= texture.width { continue; }
+
+			for dst_y in area.y..area.y + area.height {
+				let tex_y = src.y + (dst_y - dst.y) * src.height / dst.height;
+				if !texture.contains(tex_x, tex_y) { continue; }
+
+				let texel = textures.texel(code, tex_x, tex_y);
+				let pixel = match tint {
+					Some(colour) => Colour::new(colour.r, colour.g, colour.b, (texel.a as u32 * colour.a as u32 / 255) as u8),
+					None         => texel,
+				};
+
+				if pixel.a > 0 { self.buf.blend(dst_x, dst_y, pixel); }
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::render::{ ByteBuffer, PixelFormat };
+
+	#[test]
+	fn test_fill_is_clipped() {
+		let mut bytes = vec![0; 4 * 4 * 4];
+		let mut buf   = ByteBuffer::new(&mut bytes, 4, 4, 16, PixelFormat::Rgba8).unwrap();
+		let mut overlay = Overlay::new(&mut buf);
+
+		overlay.set_clip(Rect::new(1, 1, 10, 2));
+		assert_eq!(Rect::new(1, 1, 3, 2), overlay.clip());
+		overlay.fill(Rect::new(-5, -5, 20, 20), Colour::new(255, 0, 0, 255));
+
+		assert_eq!((0, 0, 0, 0), buf.get(0, 1).tuple());
+		assert_eq!((255, 0, 0, 255), buf.get(3, 2).tuple());
+		assert_eq!((0, 0, 0, 0), buf.get(3, 3).tuple());
+	}
+
+	#[test]
+	fn test_text() {
+		// a 4x2 sheet of two 2x2 glyphs, 'A' filling its cell and 'B' only the top left texel
+		let channels = [
+			[0, 0, 0, 255], [0, 0, 0, 255], [0, 0, 0, 255], [0, 0, 0, 255],
+			[0, 0, 0, 255], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0],
+		].concat();
+		let textures = TextureMap::new(4, 2, channels);
+		let font     = BitmapFont::new(0, 2, 2, 'A', 2).unwrap();
+
+		let mut bytes = vec![0; 4 * 2 * 4];
+		let mut buf   = ByteBuffer::new(&mut bytes, 4, 2, 16, PixelFormat::Rgba8).unwrap();
+		Overlay::new(&mut buf).text(&textures, &font, "BA", 0, 0, Colour::new(0, 255, 0, 255));
+
+		assert_eq!((0, 255, 0, 255), buf.get(0, 0).tuple());
+		assert_eq!((0, 0, 0, 0), buf.get(1, 1).tuple());
+		assert_eq!((0, 255, 0, 255), buf.get(3, 1).tuple());
+	}
+}
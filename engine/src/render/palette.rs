@@ -0,0 +1,135 @@
+use crate::render::{ Colour, Fog, Shade };
+use serde_json;
+
+// the last entry of every palette is kept for transparent texels
+pub const TRANSPARENT_INDEX: u8 = 255;
+
+// number of steps light and fog are divided into when shading palette indices
+const COLORMAP_LEVELS: usize = 32;
+
+// index of a colour in a palette, drawn in place of the colour itself
+#[derive(Copy, Clone, PartialEq)]
+pub struct Indexed(pub u8);
+
+#[derive(Clone)]
+pub struct Palette {
+	colours: Vec<Colour>,
+}
+
+impl Palette {
+	pub fn new(colours: Vec<Colour>) -> Result<Palette, &'static str> {
+		if colours.is_empty() || colours.len() > TRANSPARENT_INDEX as usize {
+			return Err("Palette must have between 1 and 255 colours");
+		}
+
+		// unused entries are black, and the last is transparent
+		let mut colours = colours;
+		colours.resize(TRANSPARENT_INDEX as usize, Colour::new(0, 0, 0, 0xFF));
+		colours.push(Colour::new(0, 0, 0, 0));
+
+		Ok(Palette { colours })
+	}
+
+	pub fn colour(&self, index: Indexed) -> Colour {
+		self.colours[index.0 as usize]
+	}
+
+	// index of the palette colour closest to the given one. Colours which are more than half
+	// transparent become the transparent index, as palette indices are either opaque or not
+	pub fn nearest(&self, colour: Colour) -> Indexed {
+		if colour.a < 0x80 { return Indexed(TRANSPARENT_INDEX); }
+
+		let distance = |other: &Colour| {
+			let (dr, dg, db) = (colour.r as i32 - other.r as i32, colour.g as i32 - other.g as i32, colour.b as i32 - other.b as i32);
+			dr * dr + dg * dg + db * db
+		};
+
+		let (index, _) = self.colours[..TRANSPARENT_INDEX as usize].iter().enumerate().min_by_key(|(_, other)| distance(other)).unwrap();
+		Indexed(index as u8)
+	}
+
+	// copy of the palette with every colour moved towards another by amount / 255, used for
+	// effects like the screen flashing red when hurt
+	pub fn tint(&self, colour: Colour, amount: u8) -> Palette {
+		let mut colours: Vec<Colour> = self.colours.iter().map(|other| other.lerp(&colour, amount)).collect();
+		colours[TRANSPARENT_INDEX as usize] = self.colours[TRANSPARENT_INDEX as usize];
+		Palette { colours }
+	}
+}
+
+impl TryFrom<&serde_json::Value> for Palette {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		let colours = json.as_array().ok_or("Palette must be an array of colours")?.iter()
+			.map(Colour::try_from)
+			.collect::<Result<Vec<Colour>, &'static str>>()?;
+		Palette::new(colours)
+	}
+}
+
+// lookup tables which shade palette indices, giving the index of the palette colour closest to
+// the shaded colour rather than working out the colour itself
+pub struct Colormaps {
+	light: Vec<u8>,
+	fog: Vec<u8>,
+}
+
+impl Colormaps {
+	pub fn new(palette: &Palette, fog: Option<&Fog>) -> Colormaps {
+		let black = Colour::new(0, 0, 0, 0);
+		let light = Colormaps::build(palette, |colour, level| colour.lerp(&black, 255 - level));
+		let fog   = fog.map_or(vec![], |fog| Colormaps::build(palette, |colour, level| colour.lerp(&fog.colour, level)));
+		Colormaps { light, fog }
+	}
+
+	// a table of every palette index for each level, each step brighter or foggier than the last
+	fn build(palette: &Palette, shade: impl Fn(Colour, u8) -> Colour) -> Vec<u8> {
+		let shade = &shade;
+		(0..COLORMAP_LEVELS).flat_map(|level| {
+			let level = (level * 255 / (COLORMAP_LEVELS - 1)) as u8;
+			(0..=TRANSPARENT_INDEX).map(move |index| {
+				if index == TRANSPARENT_INDEX { index } else { palette.nearest(shade(palette.colour(Indexed(index)), level)).0 }
+			})
+		}).collect()
+	}
+
+	// darken first so fog keeps its own colour in unlit areas, as Lighting does for colours. The
+	// tables can't tint, so point lights only brighten by their strongest channel
+	pub fn apply(&self, index: Indexed, shade: Shade) -> Indexed {
+		let level = |amount: u8| amount as usize * (COLORMAP_LEVELS - 1) / 255 * 256;
+		let light = shade.light.saturating_add(shade.glow.r.max(shade.glow.g).max(shade.glow.b));
+		let index = self.light[level(light) + index.0 as usize];
+		if self.fog.is_empty() { return Indexed(index); }
+		Indexed(self.fog[level(shade.fog) + index as usize])
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn greys() -> Palette {
+		Palette::new((0..=254).map(|level| Colour::new(level, level, level, 0xFF)).collect()).unwrap()
+	}
+
+	#[test]
+	fn test_nearest() {
+		let palette = greys();
+		assert_eq!(100, palette.nearest(Colour::new(98, 100, 102, 0xFF)).0);
+		assert_eq!(254, palette.nearest(Colour::new(255, 255, 255, 0xFF)).0);
+		assert_eq!(TRANSPARENT_INDEX, palette.nearest(Colour::new(255, 255, 255, 0)).0);
+	}
+
+	#[test]
+	fn test_colormaps() {
+		let palette   = greys();
+		let colormaps = Colormaps::new(&palette, None);
+		let black     = Colour::new(0, 0, 0, 0);
+
+		assert_eq!(200, colormaps.apply(Indexed(200), Shade { light: 255, fog: 0, glow: black }).0);
+		assert_eq!(0, colormaps.apply(Indexed(200), Shade { light: 0, fog: 0, glow: black }).0);
+		assert!((colormaps.apply(Indexed(200), Shade { light: 128, fog: 0, glow: black }).0 as i32 - 100).abs() <= 4);
+		assert_eq!(TRANSPARENT_INDEX, colormaps.apply(Indexed(TRANSPARENT_INDEX), Shade { light: 0, fog: 0, glow: black }).0);
+	}
+}
@@ -1,23 +1,65 @@
-use crate::scene::{ Tile, Scene };
+use crate::render::{ Camera, Viewport };
+use crate::scene::{ PushWall, Tile, Scene };
 use crate::trig;
 use itertools::Itertools;
 use shared::consts;
 use shared::fp;
 use shared::fp::{ ToFixedPoint, FromFixedPoint };
 
+// the way a surface hit by a ray looks out, towards the ray. North is towards the top of the
+// scene, where y is smallest
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Face {
+	North,
+	South,
+	East,
+	West,
+	Up,   // floors
+	Down, // ceilings
+}
+
+impl Face {
+	// textures run left to right as seen from in front of a wall, which is backwards along
+	// the grid for walls looking north or east
+	pub fn is_reversed(&self) -> bool {
+		matches!(self, Face::North | Face::East)
+	}
+
+	// unit step away from the surface along the grid, towards whatever sees it. Floors and
+	// ceilings face along the height, so have no step across the grid
+	pub fn normal(&self) -> (i32, i32) {
+		match self {
+			Face::North       => (0, -1),
+			Face::South       => (0, 1),
+			Face::East        => (1, 0),
+			Face::West        => (-1, 0),
+			Face::Up | Face::Down => (0, 0),
+		}
+	}
+}
+
 #[derive(Copy, Clone)]
 pub struct Intersection {
 	pub x: i32,
 	pub y: i32,
 	pub dist: i32,
 	pub texture: u32,
+	pub animation: Option<u32>, // shown in place of the texture when the surface is animated
 	pub texture_column: i32,
 	pub reverse: bool,
+	pub cell_x: i32,
+	pub cell_y: i32,
+	pub height: i32,
+	pub face: Face,
+	pub tile_x: i32, // grid coordinate of the tile hit, which for walls on a grid line is not the
+	pub tile_y: i32, // cell they are seen from but the one they are stored with in the scene
 }
 
 impl Intersection {
-	pub fn new(x: i32, y: i32, dist:i32, texture: u32, texture_column: i32, reverse: bool) -> Intersection {
-		Intersection { x, y, dist, texture, texture_column, reverse }
+	// cell is the tile the surface is seen from, which decides how brightly it is lit
+	pub fn new(x: i32, y: i32, dist:i32, texture: u32, texture_column: i32, face: Face, cell: (i32, i32)) -> Intersection {
+		let reverse = face.is_reversed();
+		Intersection { x, y, dist, texture, animation: None, texture_column, reverse, cell_x: cell.0, cell_y: cell.1, height: consts::WALL_HEIGHT, face, tile_x: cell.0, tile_y: cell.1 }
 	}
 }
 
@@ -30,12 +72,14 @@ struct RayMeta<'a> {
 	pub step_y: i32,        // distance to next horizontal intersect
 	pub x: i32,             // x coordinate of current ray intersect
 	pub y: i32,             // y coordinate of current ray intersect
-	pub flipped: bool,      // should the texture of the encountered surface be rendered backwards
+	pub face: Face,         // which way the walls the ray hits look, back towards it
 	pub direction: i32,     // direction in which the ray is cast
 	pub scene: &'a Scene,   // the environment in which the ray is being cast
 	pub origin_x: i32,      // x point of origin of the ray in fixed point representation
 	pub origin_y: i32,      // y point of origin of the ray in fixed point representation
-	pub sweep: i32,
+	pub fisheye: i32,       // fisheye correction for the column the ray is cast through
+	pub band: bool,         // is the next check for walls inside the cells between this line and the next
+	pub hits: Vec<Intersection>, // intersections found inside the current band of cells, nearest last
 }
 
 struct RayH<'a> {
@@ -43,31 +87,33 @@ struct RayH<'a> {
 }
 
 impl RayH<'_> {
-	pub fn new(origin_x: i32, origin_y: i32, direction: i32, sweep: i32, scene: &Scene) -> RayH {
+	pub fn new(origin_x: i32, origin_y: i32, direction: i32, fisheye: i32, scene: &Scene) -> RayH<'_> {
 		let step_x: i32;
 		let step_y: i32;
 		let x: i32;
 		let y: i32;
-		let flipped: bool;
+		let face: Face;
 
 		// determine if looking up or down and find horizontal intersection
 		if direction > trig::ANGLE_0 && direction < trig::ANGLE_180 { // looking down
 			step_x = trig::x_step(direction);
 			step_y = consts::FP_TILE_SIZE;
 
-			y = ((origin_y.to_i32() / consts::TILE_SIZE) * consts::TILE_SIZE + consts::TILE_SIZE).to_fp();
+			y = ((origin_y.to_i32() / consts::TILE_SIZE) * consts::TILE_SIZE).to_fp();
 			x = fp::add(origin_x, fp::mul(fp::sub(y, origin_y), trig::itan(direction)));
-			flipped = true;
+			face = Face::North;
 		} else {                     // looking up
 			step_x = trig::x_step(direction);
 			step_y = -consts::FP_TILE_SIZE;
 
-			y = ((origin_y.to_i32() / consts::TILE_SIZE) * consts::TILE_SIZE).to_fp();
+			y = ((origin_y.to_i32() / consts::TILE_SIZE) * consts::TILE_SIZE + consts::TILE_SIZE).to_fp();
 			x = fp::add(origin_x, fp::mul(fp::sub(y, origin_y), trig::itan(direction)));
-			flipped = false;
+			face = Face::South;
 		}
 
-		let meta = RayMeta { step_x, step_y, x, y, flipped, direction, scene, origin_x, origin_y, sweep };
+		// the ray starts on the line behind the camera so that walls set back into the camera's
+		// own cell are found, the line itself is skipped
+		let meta = RayMeta { step_x, step_y, x, y, face, direction, scene, origin_x, origin_y, fisheye, band: true, hits: Vec::new() };
 		RayH { meta }
 	}
 }
@@ -82,66 +128,113 @@ impl Iterator for RayH<'_> {
 	type Item = Intersection;
 	
 	fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-		let mut result = None;
+		let mut result = self.meta.hits.pop();
+
+		while result.is_none() {
+			if self.meta.band {
+				self.find_band_intersections();
+				self.meta.x    = fp::add(self.meta.x, self.meta.step_x);
+				self.meta.y    = fp::add(self.meta.y, self.meta.step_y);
+				self.meta.band = false;
+				result = self.meta.hits.pop();
+				continue;
+			}
 
-		while !result.is_some() {
 			let grid_x = fp::div(self.meta.x, consts::FP_TILE_SIZE).to_i32();
 			let grid_y = fp::div(self.meta.y, consts::FP_TILE_SIZE).to_i32();
 			
 			match self.meta.scene.y_wall(grid_x, grid_y) {
-				Tile::Surface(wall) => {
+				Tile::Surface(wall) if wall.offset == 0 => {
 					let world_x  = self.meta.x.to_i32();
 					let world_y  = self.meta.y.to_i32();
 					let distance = fp::mul(fp::sub(self.meta.y, self.meta.origin_y), trig::isin(self.meta.direction)).abs();
-					let distance = fp::div(distance, trig::fisheye_correction(self.meta.sweep)).to_i32();
+					let distance = fp::div(distance, self.meta.fisheye);
 					let texture  = wall.texture;
 					let texture_column = world_x & (consts::TILE_SIZE - 1);
-					result = Some(Intersection::new(world_x, world_y, distance, texture, texture_column, self.meta.flipped));
+					let cell_y   = if self.meta.step_y > 0 { grid_y - 1 } else { grid_y };
+					result = Some(Intersection { height: wall.height, animation: wall.animation, tile_y: grid_y, ..Intersection::new(world_x, world_y, distance, texture, texture_column, self.meta.face, (grid_x, cell_y)) });
 				},
 				Tile::OutOfBounds => break,
-				Tile::Empty => {}
+				_ => {}
 			}
 
-			self.meta.x = fp::add(self.meta.x, self.meta.step_x);
-			self.meta.y = fp::add(self.meta.y, self.meta.step_y);
+			self.meta.band = true;
 		}
 
 		result
 	}
 }
 
+impl RayH<'_> {
+	// walls set back from their grid line, and doors, lie inside the band of cells between
+	// the current line and the next. The ray may cross several columns of the band, so each
+	// is checked and the hits sorted by distance
+	fn find_band_intersections(&mut self) {
+		let y_end   = fp::add(self.meta.y, self.meta.step_y);
+		let x_end   = fp::add(self.meta.x, self.meta.step_x);
+		let grid_y  = fp::div(std::cmp::min(self.meta.y, y_end), consts::FP_TILE_SIZE).to_i32();
+		let col_min = fp::div(std::cmp::min(self.meta.x, x_end), consts::FP_TILE_SIZE).to_i32().max(0);
+		let col_max = fp::div(std::cmp::max(self.meta.x, x_end), consts::FP_TILE_SIZE).to_i32().min(self.meta.scene.width() - 1);
+
+		for grid_x in col_min..=col_max {
+			let (offset, texture, animation, open, height) = match self.meta.scene.y_wall(grid_x, grid_y) {
+				Tile::Surface(wall) if wall.offset > 0 => (wall.offset, wall.texture, wall.animation, 0, wall.height),
+				Tile::Door(door) => (consts::TILE_SIZE >> 1, door.texture, None, door.open_amount(), consts::WALL_HEIGHT),
+				_ => continue,
+			};
+
+			let y     = (grid_y * consts::TILE_SIZE + offset).to_fp();
+			let x     = fp::add(self.meta.origin_x, fp::mul(fp::sub(y, self.meta.origin_y), trig::itan(self.meta.direction)));
+			let ahead = if self.meta.step_y > 0 { y > self.meta.origin_y } else { y < self.meta.origin_y };
+			if !ahead || fp::div(x, consts::FP_TILE_SIZE).to_i32() != grid_x { continue; }
+
+			// the part of a door which has slid open can be seen through
+			let world_x = x.to_i32();
+			let column  = world_x & (consts::TILE_SIZE - 1);
+			if column < open { continue; }
+
+			let distance = fp::mul(fp::sub(y, self.meta.origin_y), trig::isin(self.meta.direction)).abs();
+			let distance = fp::div(distance, self.meta.fisheye);
+			self.meta.hits.push(Intersection { height, animation, ..Intersection::new(world_x, y.to_i32(), distance, texture, column - open, self.meta.face, (grid_x, grid_y)) });
+		}
+
+		self.meta.hits.sort_by_key(|hit| std::cmp::Reverse(hit.dist));
+	}
+}
+
 struct RayV<'a> {
 	meta: RayMeta<'a>,
 }
 
 impl RayV<'_> {
-	pub fn new(origin_x: i32, origin_y: i32, direction: i32, sweep: i32, scene: &Scene) -> RayV {
+	pub fn new(origin_x: i32, origin_y: i32, direction: i32, fisheye: i32, scene: &Scene) -> RayV<'_> {
 		let step_x: i32; // distance to next vertical intersect
 		let step_y: i32; // distance to next horizontal intersect
 		let x: i32;      // x coordinate of current ray intersect
 		let y: i32;      // y coordinate of current ray intersect
-		let flipped: bool;
+		let face: Face;
 
 		// determine if looking left or right and find vertical intersection
 		if direction <= trig::ANGLE_90 || direction > trig::ANGLE_270 { // looking right
 			step_x = consts::FP_TILE_SIZE;
 			step_y = trig::y_step(direction);
 			
-			x = ((origin_x.to_i32() / consts::TILE_SIZE) * consts::TILE_SIZE + consts::TILE_SIZE).to_fp();
+			x = ((origin_x.to_i32() / consts::TILE_SIZE) * consts::TILE_SIZE).to_fp();
 			y = fp::add(origin_y, fp::mul(fp::sub(x, origin_x), trig::tan(direction)));
 			
-			flipped = false;
+			face = Face::West;
 		} else {
 			step_x = -consts::FP_TILE_SIZE;
 			step_y = trig::y_step(direction);
 			
-			x = (((origin_x.to_i32() / consts::TILE_SIZE) * consts::TILE_SIZE)).to_fp();
+			x = ((origin_x.to_i32() / consts::TILE_SIZE) * consts::TILE_SIZE + consts::TILE_SIZE).to_fp();
 			y = fp::add(origin_y, fp::mul(fp::sub(x, origin_x), trig::tan(direction)));
 			
-			flipped = true;
+			face = Face::East;
 		};
 
-		let meta = RayMeta { step_x, step_y, x, y, flipped, direction, scene, origin_x, origin_y, sweep };
+		// as with horizontal rays, start on the line behind the camera to find walls set back into its cell
+		let meta = RayMeta { step_x, step_y, x, y, face, direction, scene, origin_x, origin_y, fisheye, band: true, hits: Vec::new() };
 		RayV { meta }
 	}
 }
@@ -156,110 +249,338 @@ impl Iterator for RayV<'_> {
 	type Item = Intersection;
 	
 	fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-		let mut result = None;
+		let mut result = self.meta.hits.pop();
+
+		while result.is_none() {
+			if self.meta.band {
+				self.find_band_intersections();
+				self.meta.x    = fp::add(self.meta.x, self.meta.step_x);
+				self.meta.y    = fp::add(self.meta.y, self.meta.step_y);
+				self.meta.band = false;
+				result = self.meta.hits.pop();
+				continue;
+			}
 
-		while !result.is_some() {
 			let grid_x = fp::div(self.meta.x, consts::FP_TILE_SIZE).to_i32();
 			let grid_y = fp::div(self.meta.y, consts::FP_TILE_SIZE).to_i32();
 
 			match self.meta.scene.x_wall(grid_x, grid_y) {
-				Tile::Surface(wall) => {					
+				Tile::Surface(wall) if wall.offset == 0 => {
 					let world_x  = self.meta.x.to_i32();
 					let world_y  = self.meta.y.to_i32();
 					let distance = fp::mul(fp::sub(self.meta.x, self.meta.origin_x), trig::icos(self.meta.direction)).abs();
-					let distance = fp::div(distance, trig::fisheye_correction(self.meta.sweep)).to_i32();
+					let distance = fp::div(distance, self.meta.fisheye);
 					let texture  = wall.texture;
 					let texture_column = world_y & (consts::TILE_SIZE - 1);
-					result = Some(Intersection::new(world_x, world_y, distance, texture, texture_column, self.meta.flipped));
+					let cell_x   = if self.meta.step_x > 0 { grid_x - 1 } else { grid_x };
+					result = Some(Intersection { height: wall.height, animation: wall.animation, tile_x: grid_x, ..Intersection::new(world_x, world_y, distance, texture, texture_column, self.meta.face, (cell_x, grid_y)) });
 				},
 				Tile::OutOfBounds => break,
-				Tile::Empty => {}
+				_ => {}
 			}
 
-			self.meta.x = fp::add(self.meta.x, self.meta.step_x);
-			self.meta.y = fp::add(self.meta.y, self.meta.step_y);
+			self.meta.band = true;
 		}
 
 		result
 	}
 }
 
-pub fn find_wall_intersections(origin_x: i32, origin_y: i32, direction: i32, sweep: i32, scene: &Scene) -> Vec<Intersection> {
-	let ray_h = RayH::new(origin_x, origin_y, direction, sweep, scene);
-	let ray_v = RayV::new(origin_x, origin_y, direction, sweep, scene);
+impl RayV<'_> {
+	// as for horizontal rays, but the band is a column of cells and the ray may cross several rows of it
+	fn find_band_intersections(&mut self) {
+		let x_end   = fp::add(self.meta.x, self.meta.step_x);
+		let y_end   = fp::add(self.meta.y, self.meta.step_y);
+		let grid_x  = fp::div(std::cmp::min(self.meta.x, x_end), consts::FP_TILE_SIZE).to_i32();
+		let row_min = fp::div(std::cmp::min(self.meta.y, y_end), consts::FP_TILE_SIZE).to_i32().max(0);
+		let row_max = fp::div(std::cmp::max(self.meta.y, y_end), consts::FP_TILE_SIZE).to_i32().min(self.meta.scene.height() - 1);
+
+		for grid_y in row_min..=row_max {
+			let (offset, texture, animation, open, height) = match self.meta.scene.x_wall(grid_x, grid_y) {
+				Tile::Surface(wall) if wall.offset > 0 => (wall.offset, wall.texture, wall.animation, 0, wall.height),
+				Tile::Door(door) => (consts::TILE_SIZE >> 1, door.texture, None, door.open_amount(), consts::WALL_HEIGHT),
+				_ => continue,
+			};
+
+			let x     = (grid_x * consts::TILE_SIZE + offset).to_fp();
+			let y     = fp::add(self.meta.origin_y, fp::mul(fp::sub(x, self.meta.origin_x), trig::tan(self.meta.direction)));
+			let ahead = if self.meta.step_x > 0 { x > self.meta.origin_x } else { x < self.meta.origin_x };
+			if !ahead || fp::div(y, consts::FP_TILE_SIZE).to_i32() != grid_y { continue; }
+
+			// the part of a door which has slid open can be seen through
+			let world_y = y.to_i32();
+			let column  = world_y & (consts::TILE_SIZE - 1);
+			if column < open { continue; }
+
+			let distance = fp::mul(fp::sub(x, self.meta.origin_x), trig::icos(self.meta.direction)).abs();
+			let distance = fp::div(distance, self.meta.fisheye);
+			self.meta.hits.push(Intersection { height, animation, ..Intersection::new(x.to_i32(), world_y, distance, texture, column - open, self.meta.face, (grid_x, grid_y)) });
+		}
+
+		self.meta.hits.sort_by_key(|hit| std::cmp::Reverse(hit.dist));
+	}
+}
 
-	if ray_h.is_undefined() { return ray_v.collect(); }
-	if ray_v.is_undefined() { return ray_h.collect(); }
+pub fn find_wall_intersections(origin_x: i32, origin_y: i32, direction: i32, column: i32, viewport: &Viewport, scene: &Scene) -> Vec<Intersection> {
+	let fisheye = viewport.fisheye_correction(column);
+	let ray_h = RayH::new(origin_x, origin_y, direction, fisheye, scene);
+	let ray_v = RayV::new(origin_x, origin_y, direction, fisheye, scene);
+
+	let mut intersections: Vec<Intersection> = if ray_h.is_undefined() {
+		ray_v.collect()
+	} else if ray_v.is_undefined() {
+		ray_h.collect()
+	} else {
+		ray_h.merge_by(ray_v, |a, b| a.dist < b.dist).collect()
+	};
+
+	// sliding push walls lie between grid lines so are intersected separately
+	if !scene.push_walls().is_empty() {
+		intersections.extend(scene.push_walls().iter().filter_map(|block| find_push_wall_intersection(origin_x, origin_y, direction, fisheye, block)));
+		intersections.sort_by_key(|intersection| intersection.dist);
+	}
 
-	ray_h.merge_by(ray_v, |a, b| a.dist < b.dist).collect()
+	intersections
 }
 
-pub fn find_floor_intersection(origin_x: i32, origin_y: i32, direction: i32, row: i32, column: i32, scene: &Scene) -> Option<Intersection> {
-	// convert to fixed point
-	let player_height = consts::PLAYER_HEIGHT.to_fp(); 
-	let pp_distance   = consts::DISTANCE_TO_PROJECTION_PLANE.to_fp();
+// nearest face of a sliding push wall hit by a ray
+fn find_push_wall_intersection(origin_x: i32, origin_y: i32, direction: i32, fisheye: i32, block: &PushWall) -> Option<Intersection> {
+	let mut result: Option<Intersection> = None;
+
+	// left or right face
+	if direction != trig::ANGLE_90 && direction != trig::ANGLE_270 {
+		let looking_right  = !(trig::ANGLE_90..=trig::ANGLE_270).contains(&direction);
+		let (x, side)      = if looking_right { (block.x, 0) } else { (fp::add(block.x, consts::FP_TILE_SIZE), 1) };
+		let y              = fp::add(origin_y, fp::mul(fp::sub(x, origin_x), trig::tan(direction)));
+		let ahead          = if looking_right { x > origin_x } else { x < origin_x };
+
+		if ahead && y >= block.y && y < fp::add(block.y, consts::FP_TILE_SIZE) {
+			let distance = fp::mul(fp::sub(x, origin_x), trig::icos(direction)).abs();
+			let distance = fp::div(distance, fisheye);
+			let cell_x   = if looking_right { (x.to_i32() - 1) / consts::TILE_SIZE } else { x.to_i32() / consts::TILE_SIZE };
+			let column   = fp::sub(y, block.y).to_i32();
+			let face     = if looking_right { Face::West } else { Face::East };
+			result = Some(Intersection { animation: block.animations[side], ..Intersection::new(x.to_i32(), y.to_i32(), distance, block.textures[side], column, face, (cell_x, y.to_i32() / consts::TILE_SIZE)) });
+		}
+	}
+
+	// top or bottom face
+	if direction != trig::ANGLE_0 && direction != trig::ANGLE_180 {
+		let looking_down   = direction > trig::ANGLE_0 && direction < trig::ANGLE_180;
+		let (y, side)      = if looking_down { (block.y, 2) } else { (fp::add(block.y, consts::FP_TILE_SIZE), 3) };
+		let x              = fp::add(origin_x, fp::mul(fp::sub(y, origin_y), trig::itan(direction)));
+		let ahead          = if looking_down { y > origin_y } else { y < origin_y };
+
+		if ahead && x >= block.x && x < fp::add(block.x, consts::FP_TILE_SIZE) {
+			let distance = fp::mul(fp::sub(y, origin_y), trig::isin(direction)).abs();
+			let distance = fp::div(distance, fisheye);
+
+			if result.is_none_or(|intersection| distance < intersection.dist) {
+				let cell_y = if looking_down { (y.to_i32() - 1) / consts::TILE_SIZE } else { y.to_i32() / consts::TILE_SIZE };
+				let column = fp::sub(x, block.x).to_i32();
+				let face   = if looking_down { Face::North } else { Face::South };
+				result = Some(Intersection { animation: block.animations[side], ..Intersection::new(x.to_i32(), y.to_i32(), distance, block.textures[side], column, face, (x.to_i32() / consts::TILE_SIZE, cell_y)) });
+			}
+		}
+	}
+
+	result
+}
+
+// distance along the centre ray to the floor or ceiling seen through a row of the display, where
+// height is the distance between the eye and that surface and rows are counted away from the horizon
+fn row_distance(height: i32, row: i32, viewport: &Viewport) -> i32 {
+	let pp_distance = viewport.distance_to_projection_plane().to_fp();
 
 	// adding 1 to the row exactly on the horizon avoids a division by one error
 	// doubles up the texture at the vanishing point, but probably fine
-	let row = if row == consts::PROJECTION_PLANE_HORIZON { (row + 1).to_fp() } else { row.to_fp() };
+	let row = if row == 0 { 1.to_fp() } else { row.to_fp() };
 
-	let ratio = fp::div(player_height, fp::sub(row, consts::PROJECTION_PLANE_HORIZON.to_fp()));
+	fp::floor(fp::mul(pp_distance, fp::div(height.to_fp(), row)))
+}
 
-	let distance = fp::mul(fp::floor(fp::mul(pp_distance, ratio)), trig::fisheye_correction(column));
+// the floor or ceiling tile at a point in world space and the texel the point lands on
+fn surface_at(world_x: i32, world_y: i32, distance: i32, scene: &Scene, face: Face) -> Option<Intersection> {
+	let x = world_x >> consts::TILE_SHIFT;
+	let y = world_y >> consts::TILE_SHIFT;
 
-	let x_end = fp::floor(fp::mul(distance, trig::cos(direction)));
-	let y_end = fp::floor(fp::mul(distance, trig::sin(direction)));
+	let tex_x = world_x & (consts::TILE_SIZE - 1);
+	let tex_y = world_y & (consts::TILE_SIZE - 1);
 
-	let x_end = fp::add(origin_x, x_end);
-	let y_end = fp::add(origin_y, y_end);
-	
-	let x = fp::floor(fp::div(x_end, consts::FP_TILE_SIZE)).to_i32();
-	let y = fp::floor(fp::div(y_end, consts::FP_TILE_SIZE)).to_i32();
-	
-	let tex_x = x_end.to_i32() & (consts::TILE_SIZE - 1);
-	let tex_y = y_end.to_i32() & (consts::TILE_SIZE - 1);
-
-	match scene.floor(x, y) {
-		Tile::Surface(floor) => Some(Intersection::new(tex_x, tex_y, distance, floor.texture, 0, false)),
+	let surface = if face == Face::Up { scene.floor(x, y) } else { scene.ceiling(x, y) };
+	match surface {
+		Tile::Surface(tile) => Some(Intersection { animation: tile.animation, ..Intersection::new(tex_x, tex_y, distance, tile.texture, 0, face, (x, y)) }),
 		_ => None,
 	}
 }
 
-pub fn find_ceiling_intersection(origin_x: i32, origin_y: i32, direction: i32, row: i32, column: i32, scene: &Scene) -> Option<Intersection> {
-	// convert to fixed point
-	let player_height = consts::PLAYER_HEIGHT.to_fp(); 
-	let pp_distance   = consts::DISTANCE_TO_PROJECTION_PLANE.to_fp();
-	let wall_height   = consts::WALL_HEIGHT.to_fp();
+// fractional bits in the directions of rays through the projection plane. More than the 16 of
+// fixed point elsewhere, so that stepping a whole row of columns along the plane doesn't drift
+const PLANE_SHIFT: i64 = 24;
+
+// directions of the rays which floors and ceilings are cast along, through the centre of each
+// column of the projection plane. The walls' rays are rounded to the angles of the trig tables,
+// so turn by uneven amounts from column to column, whereas these move the same distance across
+// the plane every column, which is what lets a row of the floor be stepped across
+struct PlaneRays {
+	x: i64,      // direction through the left column, to PLANE_SHIFT fractional bits
+	y: i64,
+	step_x: i64, // change in direction for each column to the right
+	step_y: i64,
+}
 
-	// adding 1 to the row exactly on the horizon avoids a division by one error
-	// doubles up the texture at the vanishing point, but probably fine
-	let row = if row == consts::PROJECTION_PLANE_HORIZON { (row + 1).to_fp() } else { row.to_fp() };
+impl PlaneRays {
+	fn new(camera: &Camera, viewport: &Viewport) -> PlaneRays {
+		let cos   = trig::cos(camera.angle()) as i64;
+		let sin   = trig::sin(camera.angle()) as i64;
+		let tan   = trig::tan(viewport.fov() >> 1) as i64;
+		let width = viewport.width() as i64;
 
-	let ratio = fp::div(fp::sub(wall_height, player_height), fp::sub(consts::PROJECTION_PLANE_HORIZON.to_fp(), row));
+		// the projection plane runs to the right, at right angles to the camera, and is tan across
+		// from the centre at a distance of one
+		let step   = |length: i64| (2 * tan * length / width) >> (32 - PLANE_SHIFT);
+		let step_x = -step(sin);
+		let step_y = step(cos);
 
-	let distance = fp::mul(fp::floor(fp::mul(pp_distance, ratio)), trig::fisheye_correction(column));
+		// the centre of the left column is half a column in from the edge of the plane
+		let x = (cos << (PLANE_SHIFT - 16)) + (1 - width) * step_x / 2;
+		let y = (sin << (PLANE_SHIFT - 16)) + (1 - width) * step_y / 2;
 
-	let x_end = fp::floor(fp::mul(distance, trig::cos(direction)));
-	let y_end = fp::floor(fp::mul(distance, trig::sin(direction)));
+		PlaneRays { x, y, step_x, step_y }
+	}
 
-	let x_end = fp::add(origin_x, x_end);
-	let y_end = fp::add(origin_y, y_end);
-	
-	let x = fp::floor(fp::div(x_end, consts::FP_TILE_SIZE)).to_i32();
-	let y = fp::floor(fp::div(y_end, consts::FP_TILE_SIZE)).to_i32();
-	
-	let tex_x = x_end.to_i32() & (consts::TILE_SIZE - 1);
-	let tex_y = y_end.to_i32() & (consts::TILE_SIZE - 1);
+	fn at(&self, column: i32) -> (i64, i64) {
+		(self.x + column as i64 * self.step_x, self.y + column as i64 * self.step_y)
+	}
+}
 
-	match scene.ceiling(x, y) {
-		Tile::Surface(ceiling) => Some(Intersection::new(tex_x, tex_y, distance, ceiling.texture, 0, false)),
-		_ => None,
+// the floor or ceiling, facing the given way, seen through a column and row of the display,
+// where distance is how far away the row lies along the centre ray
+fn plane_intersection(camera: &Camera, distance: i32, column: i32, viewport: &Viewport, scene: &Scene, face: Face) -> Option<Intersection> {
+	let (ray_x, ray_y) = PlaneRays::new(camera, viewport).at(column);
+
+	let shift   = 16 + PLANE_SHIFT;
+	let world_x = (((camera.x() as i64) << shift) + distance as i64 * ray_x) >> shift;
+	let world_y = (((camera.y() as i64) << shift) + distance as i64 * ray_y) >> shift;
+
+	surface_at(world_x as i32, world_y as i32, fp::mul(distance, viewport.fisheye_correction(column)), scene, face)
+}
+
+// rows are counted down from the horizon, which moves as the camera looks up and down
+pub fn find_floor_intersection(camera: &Camera, row: i32, column: i32, viewport: &Viewport, scene: &Scene) -> Option<Intersection> {
+	plane_intersection(camera, row_distance(camera.height(), row, viewport), column, viewport, scene, Face::Up)
+}
+
+// rows are counted up from the horizon, which moves as the camera looks up and down
+pub fn find_ceiling_intersection(camera: &Camera, row: i32, column: i32, viewport: &Viewport, scene: &Scene) -> Option<Intersection> {
+	plane_intersection(camera, row_distance(consts::WALL_HEIGHT - camera.height(), row, viewport), column, viewport, scene, Face::Down)
+}
+
+// distances to the floor and ceiling seen through every row of the display from an eye height.
+// They only change along with the eye height or the projection, so are worked out once rather
+// than for every pixel, and for the default display standing up come from the lookup tables
+pub struct RowDistances {
+	eye_height: i32,
+	floor: Vec<i32>,
+	ceiling: Vec<i32>,
+}
+
+impl RowDistances {
+	pub fn new(viewport: &Viewport, eye_height: i32) -> RowDistances {
+		if eye_height == consts::PLAYER_HEIGHT && viewport.is_default() {
+			let rows = trig::row_distances().to_vec();
+			return RowDistances { eye_height, floor: rows.clone(), ceiling: rows };
+		}
+
+		let floor   = (0..viewport.height()).map(|row| row_distance(eye_height, row, viewport)).collect();
+		let ceiling = (0..viewport.height()).map(|row| row_distance(consts::WALL_HEIGHT - eye_height, row, viewport)).collect();
+		RowDistances { eye_height, floor, ceiling }
+	}
+
+	pub fn eye_height(&self) -> i32 {
+		self.eye_height
+	}
+}
+
+// a row of the floor or ceiling as a line across the world, from the point seen in the left
+// column of the display, to 16 + PLANE_SHIFT fractional bits
+#[derive(Copy, Clone)]
+struct Span {
+	x: i64,
+	y: i64,
+	step_x: i64,
+	step_y: i64,
+}
+
+impl Span {
+	// the same sums plane_intersection does for each pixel, with the parts which are the same
+	// across the row done once, so stepping lands on exactly the same point
+	fn new(camera: &Camera, rays: &PlaneRays, distance: i32) -> Span {
+		let shift    = 16 + PLANE_SHIFT;
+		let distance = distance as i64;
+		Span {
+			x: ((camera.x() as i64) << shift) + distance * rays.x,
+			y: ((camera.y() as i64) << shift) + distance * rays.y,
+			step_x: distance * rays.step_x,
+			step_y: distance * rays.step_y,
+		}
+	}
+
+	fn at(&self, column: i64) -> (i32, i32) {
+		let shift = 16 + PLANE_SHIFT;
+		(((self.x + column * self.step_x) >> shift) as i32, ((self.y + column * self.step_y) >> shift) as i32)
+	}
+}
+
+// where every row of the floor and ceiling lies in the world as seen by the camera, worked out
+// once a frame from the distances to each row
+pub struct SurfaceSpans {
+	floor: Vec<Span>,
+	ceiling: Vec<Span>,
+}
+
+impl SurfaceSpans {
+	pub fn new(camera: &Camera, viewport: &Viewport, rows: &RowDistances) -> SurfaceSpans {
+		let rays = PlaneRays::new(camera, viewport);
+		let span = |distance: &i32| Span::new(camera, &rays, *distance);
+		SurfaceSpans { floor: rows.floor.iter().map(span).collect(), ceiling: rows.ceiling.iter().map(span).collect() }
+	}
+}
+
+// finds the floor and ceiling for the rows of a single column of the display, giving the same
+// intersections as find_floor_intersection and find_ceiling_intersection. Rather than casting a
+// ray, the point on each row is found by stepping along the row's span to the column
+pub struct SurfaceCaster<'a> {
+	column: i64,
+	fisheye: i32,
+	rows: &'a RowDistances,
+	spans: &'a SurfaceSpans,
+	scene: &'a Scene,
+}
+
+impl<'a> SurfaceCaster<'a> {
+	pub fn new(column: i32, viewport: &Viewport, rows: &'a RowDistances, spans: &'a SurfaceSpans, scene: &'a Scene) -> SurfaceCaster<'a> {
+		SurfaceCaster { column: column as i64, fisheye: viewport.fisheye_correction(column), rows, spans, scene }
+	}
+
+	// rows are counted down from the horizon
+	pub fn floor(&self, row: i32) -> Option<Intersection> {
+		let (x, y) = self.spans.floor[row as usize].at(self.column);
+		surface_at(x, y, fp::mul(self.rows.floor[row as usize], self.fisheye), self.scene, Face::Up)
+	}
+
+	// rows are counted up from the horizon
+	pub fn ceiling(&self, row: i32) -> Option<Intersection> {
+		let (x, y) = self.spans.ceiling[row as usize].at(self.column);
+		surface_at(x, y, fp::mul(self.rows.ceiling[row as usize], self.fisheye), self.scene, Face::Down)
 	}
 }
 
 #[cfg(test)]
 mod test {
 	use super::*;
+	use crate::render::RenderConfig;
+	use crate::scene::{ DoorTile, TextureTile };
 	use std::fs;
 	use std::path::Path;
 	use std::path::PathBuf;
@@ -276,7 +597,9 @@ mod test {
 		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
 		let scene = load_scene(&fname).expect("Failed to load scene for test");
 
-		let intersections = find_wall_intersections(128.to_fp(), 128.to_fp(), trig::ANGLE_0, consts::PROJECTION_PLANE_WIDTH / 2, &scene);
+		let viewport = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+
+		let intersections = find_wall_intersections(128.to_fp(), 128.to_fp(), trig::ANGLE_0, viewport.width() / 2, &viewport, &scene);
 
 		assert_eq!(1, intersections.len());
 
@@ -289,11 +612,237 @@ mod test {
 		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
 		let scene = load_scene(&fname).expect("Failed to load scene for test");
 
-		let intersections = find_wall_intersections(28.to_fp(), 28.to_fp(), trig::ANGLE_270, consts::PROJECTION_PLANE_WIDTH / 2, &scene);
+		let viewport = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+
+		let intersections = find_wall_intersections(28.to_fp(), 28.to_fp(), trig::ANGLE_270, viewport.width() / 2, &viewport, &scene);
 
 		assert_eq!(1, intersections.len());
 
 		let intersection = intersections[0];
 		assert_eq!(28, intersection.dist.to_i32());
 	}
+
+	#[test]
+	fn test_door_recessed_into_cell() {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let mut scene = load_scene(&fname).expect("Failed to load scene for test");
+		scene.set_door(2, 2, true, DoorTile::new(0));
+
+		let viewport = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+
+		// door in the cell ahead, then in the camera's own cell from either side
+		let intersections = find_wall_intersections(128.to_fp(), 128.to_fp(), trig::ANGLE_0, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(2, intersections.len());
+		assert_eq!(32, intersections[0].dist.to_i32());
+
+		let intersections = find_wall_intersections(140.to_fp(), 128.to_fp(), trig::ANGLE_0, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(20, intersections[0].dist.to_i32());
+
+		let intersections = find_wall_intersections(180.to_fp(), 128.to_fp(), trig::ANGLE_180, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(20, intersections[0].dist.to_i32());
+
+		// once fully open the door can be seen through
+		scene.open_door(2, 2);
+		scene.update(consts::DOOR_OPEN_TIME as u32);
+
+		let intersections = find_wall_intersections(128.to_fp(), 128.to_fp(), trig::ANGLE_0, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(1, intersections.len());
+		assert_eq!(128, intersections[0].dist.to_i32());
+	}
+
+	#[test]
+	fn test_wall_offset_into_cell() {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let mut scene = load_scene(&fname).expect("Failed to load scene for test");
+		scene.set_x_wall(2, 1, Tile::Surface(TextureTile { offset: 48, ..TextureTile::new(0) }));
+		scene.set_x_wall(2, 2, Tile::Surface(TextureTile { offset: 48, ..TextureTile::new(0) }));
+
+		let viewport = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+
+		// sorted in front of the wall on the grid line behind it
+		let intersections = find_wall_intersections(100.to_fp(), 128.to_fp(), trig::ANGLE_0, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(2, intersections.len());
+		assert_eq!(76, intersections[0].dist.to_i32());
+		assert_eq!(156, intersections[1].dist.to_i32());
+
+		// the ray crosses from row 1 into row 2 between the grid lines, so only the wall in row 2 is hit
+		let intersections = find_wall_intersections(100.to_fp(), 100.to_fp(), trig::ANGLE_30, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(176, intersections[0].x);
+		assert_eq!(2, intersections[0].cell_y);
+		assert_eq!(15, intersections[0].texture_column);
+		assert_eq!(87, intersections[0].dist.to_i32());
+	}
+
+	#[test]
+	fn test_push_wall_between_grid_lines() {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let mut scene = load_scene(&fname).expect("Failed to load scene for test");
+
+		let wall = || Tile::Surface(TextureTile::new(0));
+		scene.set_x_wall(3, 2, wall());
+		scene.set_x_wall(4, 2, wall());
+		scene.set_y_wall(3, 2, wall());
+		scene.set_y_wall(3, 3, wall());
+		scene.add_push_wall(3, 2, 1);
+
+		let viewport = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+
+		let intersections = find_wall_intersections(100.to_fp(), 128.to_fp(), trig::ANGLE_0, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(92, intersections[0].dist.to_i32());
+
+		// half way through sliding one tile left
+		assert!(scene.push_wall(3, 2, -1, 0));
+		scene.update(consts::PUSH_WALL_MOVE_TIME as u32 / 2);
+
+		let intersections = find_wall_intersections(100.to_fp(), 128.to_fp(), trig::ANGLE_0, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(60, intersections[0].dist.to_i32());
+
+		// back on the grid as ordinary walls
+		scene.update(consts::PUSH_WALL_MOVE_TIME as u32);
+		assert!(scene.push_walls().is_empty());
+
+		let intersections = find_wall_intersections(100.to_fp(), 128.to_fp(), trig::ANGLE_0, viewport.width() / 2, &viewport, &scene);
+		assert_eq!(28, intersections[0].dist.to_i32());
+	}
+
+	#[test]
+	fn test_push_wall_keeps_corridor_walls() {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let mut scene = load_scene(&fname).expect("Failed to load scene for test");
+
+		// a one tile corridor along row 2 with the block at its west end
+		for x in 1..4 {
+			scene.set_y_wall(x, 2, Tile::Surface(TextureTile::new(5)));
+			scene.set_y_wall(x, 3, Tile::Surface(TextureTile::new(5)));
+		}
+		scene.set_x_wall(1, 2, Tile::Surface(TextureTile::new(0)));
+		scene.set_x_wall(2, 2, Tile::Surface(TextureTile::new(0)));
+		scene.add_push_wall(1, 2, 2);
+
+		// only unit steps along one axis
+		assert!(!scene.push_wall(1, 2, 2, 0));
+		assert!(!scene.push_wall(1, 2, 1, 1));
+		assert!(!scene.push_wall(1, 2, 0, 0));
+
+		assert!(scene.push_wall(1, 2, 1, 0));
+		assert!(matches!(scene.x_wall(1, 2), Tile::Empty));
+		assert!(matches!(scene.x_wall(2, 2), Tile::Empty));
+
+		scene.update(2 * consts::PUSH_WALL_MOVE_TIME as u32);
+		assert!(scene.push_walls().is_empty());
+
+		// the corridor is still walled along its length, and the block is whole where it stopped
+		for x in 1..4 {
+			assert!(matches!(scene.y_wall(x, 2), Tile::Surface(_)));
+			assert!(matches!(scene.y_wall(x, 3), Tile::Surface(_)));
+		}
+		assert!(matches!(scene.x_wall(1, 2), Tile::Empty));
+		assert!(matches!(scene.x_wall(2, 2), Tile::Empty));
+		assert!(matches!(scene.x_wall(3, 2), Tile::Surface(_)));
+		assert!(matches!(scene.x_wall(4, 2), Tile::Surface(_)));
+
+		// and the corridor's walls where it stopped are still the corridor's
+		assert!(matches!(scene.y_wall(3, 2), Tile::Surface(wall) if wall.texture == 5));
+		assert!(matches!(scene.y_wall(3, 3), Tile::Surface(wall) if wall.texture == 5));
+	}
+
+	#[test]
+	fn test_push_wall_standing_alone() {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let mut scene = load_scene(&fname).expect("Failed to load scene for test");
+
+		// a block in the middle of the room, with nothing either side of it
+		scene.set_x_wall(2, 2, Tile::Surface(TextureTile::new(0)));
+		scene.set_x_wall(3, 2, Tile::Surface(TextureTile::new(1)));
+		scene.set_y_wall(2, 2, Tile::Surface(TextureTile::new(2)));
+		scene.set_y_wall(2, 3, Tile::Surface(TextureTile::new(3)));
+		scene.add_push_wall(2, 2, 1);
+
+		// every face goes with the block, leaving nothing behind
+		assert!(scene.push_wall(2, 2, 1, 0));
+		assert!(matches!(scene.x_wall(2, 2), Tile::Empty));
+		assert!(matches!(scene.x_wall(3, 2), Tile::Empty));
+		assert!(matches!(scene.y_wall(2, 2), Tile::Empty));
+		assert!(matches!(scene.y_wall(2, 3), Tile::Empty));
+
+		scene.update(consts::PUSH_WALL_MOVE_TIME as u32);
+		assert!(scene.push_walls().is_empty());
+
+		assert!(matches!(scene.y_wall(2, 2), Tile::Empty));
+		assert!(matches!(scene.y_wall(2, 3), Tile::Empty));
+		assert!(matches!(scene.x_wall(2, 2), Tile::Empty));
+
+		// and the block is whole in the next cell
+		for (tile, texture) in [(scene.x_wall(3, 2), 0), (scene.x_wall(4, 2), 1), (scene.y_wall(3, 2), 2), (scene.y_wall(3, 3), 3)] {
+			assert!(matches!(tile, Tile::Surface(wall) if wall.texture == texture));
+		}
+	}
+
+	#[test]
+	fn test_floor_and_ceiling_follow_eye_height() {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let scene = load_scene(&fname).expect("Failed to load scene for test");
+
+		let viewport   = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+		let mut camera = Camera::new(32, 32, trig::ANGLE_0, consts::PROJECTION_PLANE_HORIZON);
+
+		let floor   = find_floor_intersection(&camera, 60, viewport.width() / 2, &viewport, &scene).unwrap();
+		let ceiling = find_ceiling_intersection(&camera, 60, viewport.width() / 2, &viewport, &scene).unwrap();
+		assert_eq!(floor.dist, ceiling.dist);
+		assert_eq!(147, floor.dist.to_i32());
+
+		// crouching brings the floor closer and pushes the ceiling away
+		camera.set_height(16);
+		let floor   = find_floor_intersection(&camera, 60, viewport.width() / 2, &viewport, &scene).unwrap();
+		let ceiling = find_ceiling_intersection(&camera, 60, viewport.width() / 2, &viewport, &scene).unwrap();
+		assert_eq!(73, floor.dist.to_i32());
+		assert_eq!(221, ceiling.dist.to_i32());
+	}
+
+	#[test]
+	fn test_surface_caster_matches_intersections() {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let scene = load_scene(&fname).expect("Failed to load scene for test");
+
+		let viewport = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+
+		// stepping along a row lands on exactly the point a ray through the column would
+		let texel   = |i: Option<Intersection>| i.map(|i| ((i.cell_x, i.cell_y), i.x, i.y, i.dist));
+		let compare = |expected: Option<Intersection>, actual: Option<Intersection>| {
+			assert_eq!(texel(expected), texel(actual));
+			expected.is_some() as i32
+		};
+
+		for (angle, eye_height) in [(trig::ANGLE_30, consts::PLAYER_HEIGHT), (trig::ANGLE_180 + 17, 10)] {
+			let mut camera = Camera::new(150, 90, angle, consts::PROJECTION_PLANE_HORIZON);
+			camera.set_height(eye_height);
+			let rows  = RowDistances::new(&viewport, eye_height);
+			let spans = SurfaceSpans::new(&camera, &viewport, &rows);
+			let mut compared = 0;
+
+			for column in 0..viewport.width() {
+				let caster = SurfaceCaster::new(column, &viewport, &rows, &spans, &scene);
+
+				for row in 0..viewport.height() {
+					compared += compare(find_floor_intersection(&camera, row, column, &viewport, &scene), caster.floor(row));
+					compared += compare(find_ceiling_intersection(&camera, row, column, &viewport, &scene), caster.ceiling(row));
+				}
+			}
+
+			assert!(compared > 10000);
+		}
+	}
+
+	#[test]
+	fn test_row_distance_tables() {
+		let viewport = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+		assert!(viewport.is_default());
+
+		// the compile time tables give the same distances as working them out for the display
+		let rows = RowDistances::new(&viewport, consts::PLAYER_HEIGHT);
+		for row in 0..viewport.height() {
+			assert_eq!(row_distance(consts::PLAYER_HEIGHT, row, &viewport), rows.floor[row as usize]);
+			assert_eq!(row_distance(consts::WALL_HEIGHT - consts::PLAYER_HEIGHT, row, &viewport), rows.ceiling[row as usize]);
+		}
+	}
 }
@@ -1,55 +1,44 @@
 use base64::{Engine as _, engine::general_purpose};
 use crate::{ Camera };
-use crate::scene::{ Scene };
+use crate::scene::{ Decal, LitCells, Scene, Sprite };
 use crate::trig;
-use crate::render::raycast;
+use crate::render::{ raycast, Animation, Face, Intersection, Colormaps, FrameBuffer, Indexed, Lighting, Palette, RenderConfig, RowDistances, SeenCells, Shade, SurfaceCaster, SurfaceSpans, Viewport, TRANSPARENT_INDEX };
+use std::collections::HashMap;
 use serde_json;
 use shared::consts;
-use shared::fp::{ ToFixedPoint };
+use shared::fp;
+use shared::fp::{ ToFixedPoint, FromFixedPoint };
 
-macro_rules! colour_to_buf {
-	($colour:expr, $buf:expr, $idx:expr) => {
-		($buf[$idx + 0], $buf[$idx + 1], $buf[$idx + 2], $buf[$idx + 3]) = $colour.tuple();
-	}
-}
-
-macro_rules! blend_colour_to_buf {
-	($colour:expr, $buf:expr, $idx:expr) => {
-		let blended = $colour.blend(&Colour::new($buf[$idx + 0], $buf[$idx + 1], $buf[$idx + 2], $buf[$idx + 3]));
-		colour_to_buf!(blended, $buf, $idx);
-	}
-}
-
-macro_rules! screen_idx {
-	($x:expr, $y:expr) => {
-		4 * ($x + $y * consts::PROJECTION_PLANE_WIDTH) as usize
-	}
-}
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 macro_rules! put_surface_pixel {
-	($intersect:expr, $buf:expr, $idx:expr, $textures:expr) => {
-		if $intersect.is_some() {
-			let intersect = $intersect.unwrap();
-			let texture = $textures.get(intersect.texture, intersect.x, false);
-			let pixel = &texture[intersect.y as usize];
-			colour_to_buf!(pixel, $buf, $idx);	
+	($intersect:expr, $buf:expr, $idx:expr, $level:expr, $renderer:expr, $scene:expr, $time:expr) => {
+		if let Some(intersect) = $intersect {
+			let level = $level;
+			let texture = $renderer.texture($renderer.textures.frame(intersect.texture, intersect.animation), intersect.x, false, level);
+			let light = $scene.light_level(intersect.cell_x, intersect.cell_y, $time);
+			let glow = $scene.surface_glow_at(&$renderer.lit, intersect.cell_x * consts::TILE_SIZE + intersect.x, intersect.cell_y * consts::TILE_SIZE + intersect.y);
+			$buf[$idx] = $renderer.shade(texture[(intersect.y >> level) as usize], $renderer.lighting.shade_at(intersect.dist, light).glow(glow));
 		}
 	}
 }
 
 macro_rules! blend_surface_pixel {
-	($intersect:expr, $pixel: expr, $textures:expr) => {
-		if $intersect.is_some() {
-			let intersect = $intersect.unwrap();
-			let texture = $textures.get(intersect.texture, intersect.x, false);
-			let pixel = &texture[intersect.y as usize];
-			$pixel.blend(pixel)	
+	($intersect:expr, $pixel: expr, $level:expr, $renderer:expr, $scene:expr, $time:expr) => {
+		if let Some(intersect) = $intersect {
+			let level = $level;
+			let texture = $renderer.texture($renderer.textures.frame(intersect.texture, intersect.animation), intersect.x, false, level);
+			let light = $scene.light_level(intersect.cell_x, intersect.cell_y, $time);
+			let glow = $scene.surface_glow_at(&$renderer.lit, intersect.cell_x * consts::TILE_SIZE + intersect.x, intersect.cell_y * consts::TILE_SIZE + intersect.y);
+			$pixel.over($renderer.shade(texture[(intersect.y >> level) as usize], $renderer.lighting.shade_at(intersect.dist, light).glow(glow)))
 		} else {
 			$pixel
 		}
 	}
 }
 
+#[derive(Copy, Clone)]
 pub struct Colour {
 	pub r: u8,
 	pub g: u8,
@@ -63,10 +52,24 @@ impl Colour {
 	}
 
 	pub fn blend(self, other: &Colour) -> Colour {
-		let (r, g, b, a) = Colour::blend_colours(self.r, self.g, self.b, self.a, other.r, other.g, other.b, other.a);
+		let fa1 = self.a as f64 / 255.0;
+		let fa2 = other.a as f64 / 255.0;
+		let fao = Colour::alpha_blend(1.0, fa1, 1.0, fa2, 1.0);
+
+		let r = Colour::alpha_blend(self.r as f64, fa1, other.r as f64, fa2, fao) as u8;
+		let g = Colour::alpha_blend(self.g as f64, fa1, other.g as f64, fa2, fao) as u8;
+		let b = Colour::alpha_blend(self.b as f64, fa1, other.b as f64, fa2, fao) as u8;
+		let a = (255.0 * fao) as u8;
+
 		Colour { r, g, b, a }
 	}
 
+	// move the colour towards another by amount / 255, keeping its own alpha
+	pub fn lerp(self, other: &Colour, amount: u8) -> Colour {
+		let mix = |c1: u8, c2: u8| (c1 as i32 + (c2 as i32 - c1 as i32) * amount as i32 / 255) as u8;
+		Colour { r: mix(self.r, other.r), g: mix(self.g, other.g), b: mix(self.b, other.b), a: self.a }
+	}
+
 	pub fn tuple(&self) -> (u8, u8, u8, u8) {
 		(self.r, self.g, self.b, self.a)
 	}
@@ -74,64 +77,314 @@ impl Colour {
 	fn alpha_blend(c1: f64, a1: f64, c2: f64, a2: f64, ao: f64) -> f64 {
 		(c1 * a1 + c2 * a2 * (1.0 - a1)) / ao
 	}
+}
 
-	fn blend_colours(r1: u8, g1: u8, b1: u8, a1: u8, r2:u8, g2:u8, b2:u8, a2:u8) -> (u8, u8, u8, u8) {
-		let fa1 = a1 as f64 / 255.0;
-		let fa2 = a2 as f64 / 255.0;
-		let fao = Colour::alpha_blend(1.0, fa1, 1.0, fa2, 1.0);
+impl TryFrom<&serde_json::Value> for Colour {
+	type Error = &'static str;
 
-		let r = Colour::alpha_blend(r1 as f64, fa1, r2 as f64, fa2, fao) as u8;
-		let g = Colour::alpha_blend(g1 as f64, fa1, g2 as f64, fa2, fao) as u8;
-		let b = Colour::alpha_blend(b1 as f64, fa1, b2 as f64, fa2, fao) as u8;
-		let a = (255.0 * fao) as u8;
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		// colours are written as [r, g, b] or [r, g, b, a]
+		let channels: Vec<u8> = json.as_array().ok_or("Colour must be an array of channels")?.iter()
+			.map(|channel| channel.as_u64().map(|channel| channel.min(255) as u8))
+			.collect::<Option<Vec<u8>>>()
+			.ok_or("Colour channels must be positive integers")?;
+
+		match channels[..] {
+			[r, g, b]    => Ok(Colour::new(r, g, b, 0xFF)),
+			[r, g, b, a] => Ok(Colour::new(r, g, b, a)),
+			_            => Err("Colour must have three or four channels"),
+		}
+	}
+}
+
+// a pixel as it is drawn, either a colour or the index of one in a palette
+pub trait Pixel: Copy + Send + Sync {
+	fn clear() -> Self;
+	fn is_clear(&self) -> bool;
+	fn is_opaque(&self) -> bool;
+
+	// this pixel drawn in front of another
+	fn over(self, below: Self) -> Self;
+}
+
+impl Pixel for Colour {
+	fn clear() -> Colour {
+		Colour::new(0, 0, 0, 0)
+	}
+
+	fn is_clear(&self) -> bool {
+		self.a == 0
+	}
+
+	fn is_opaque(&self) -> bool {
+		self.a == 255
+	}
+
+	fn over(self, below: Colour) -> Colour {
+		self.blend(&below)
+	}
+}
+
+// palette indices are either opaque or transparent, so there is nothing to mix
+impl Pixel for Indexed {
+	fn clear() -> Indexed {
+		Indexed(TRANSPARENT_INDEX)
+	}
+
+	fn is_clear(&self) -> bool {
+		!self.is_opaque()
+	}
+
+	fn is_opaque(&self) -> bool {
+		self.0 != TRANSPARENT_INDEX
+	}
+
+	fn over(self, below: Indexed) -> Indexed {
+		if self.is_opaque() { self } else { below }
+	}
+}
+
+// what was drawn at a pixel of the display. Walls are given by their place in the scene's
+// x walls, for those facing east or west, or y walls, along with the side that was seen
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Pick {
+	Nothing,
+	Wall { x: i32, y: i32, face: Face },
+	Floor { x: i32, y: i32 },
+	Ceiling { x: i32, y: i32 },
+	Sky,
+	Sprite(usize), // index into the scene's sprites
+}
 
-		(r, g, b, a)
+impl Pick {
+	// the floor or ceiling a ray landed on, or else the sky if there is one
+	fn surface(intersect: &Option<Intersection>, sky: bool) -> Pick {
+		match intersect {
+			Some(intersect) if intersect.face == Face::Up => Pick::Floor { x: intersect.cell_x, y: intersect.cell_y },
+			Some(intersect)                               => Pick::Ceiling { x: intersect.cell_x, y: intersect.cell_y },
+			None if sky                                   => Pick::Sky,
+			None                                          => Pick::Nothing,
+		}
 	}
 }
 
-pub struct RenderParameters<'a> {
-	texture: &'a [Colour],
-	tex_idx: &'a [usize],
+// the column of a decal crossing a wall column, with the height of its bottom edge above the floor
+struct DecalColumn<'a, P> {
+	texture: &'a [P],
+	bottom: i32,
+	size: i32,
+}
+
+impl<P: Copy> DecalColumn<'_, P> {
+	// texel of the decal at a height above the floor, if it covers that height
+	fn texel(&self, height: i32, level: usize) -> Option<P> {
+		let offset = height - self.bottom;
+		if offset < 0 || offset >= self.size { return None; }
+		let row = (self.size - 1 - offset) * consts::TEXTURE_HEIGHT as i32 / self.size;
+		Some(self.texture[row as usize >> level])
+	}
+}
+
+pub struct RenderParameters<'a, P = Colour> {
+	texture: &'a [P],
+	decals: Vec<DecalColumn<'a, P>>,
+	shade: Shade,
+	tex_step: i32,
+	dist: i32,
+	y_top: i32,
 	y_min: i32,
 	y_max: i32,
+	tex_offset: i32,
+	tex_max: i32,
+	level: usize,
+	pick: Pick,
 }
 
-impl RenderParameters<'_> {
-	pub fn new<'a>(texture: &'a [Colour], tex_idx: &'a [usize], y_min: i32, y_max: i32,) -> RenderParameters<'a> {
-		RenderParameters { texture, tex_idx, y_min, y_max }
+impl<'a, P: Pixel> RenderParameters<'a, P> {
+	pub fn new(texture: &'a [P], shade: Shade, tex_step: i32, dist: i32, y_top: i32, y_min: i32, y_max: i32) -> RenderParameters<'a, P> {
+		RenderParameters { texture, decals: Vec::new(), shade, tex_step, dist, y_top, y_min, y_max, tex_offset: 0, tex_max: consts::TEXTURE_HEIGHT as i32 - 1, level: 0, pick: Pick::Nothing }
+	}
+
+	// repeat the texture down a wall of the given height in world units rather than
+	// stretching it, lined up with the bottom of the wall
+	pub fn tile(mut self, height: i32) -> Self {
+		let texture_height = consts::TEXTURE_HEIGHT as i32;
+		self.tex_offset = (texture_height - height % texture_height) % texture_height;
+		self.tex_max    = self.tex_offset + height - 1;
+		self
+	}
+
+	// the texture column is taken from the given mip level rather than the full size texture
+	pub fn mip(mut self, level: usize) -> Self {
+		self.level = level;
+		self
+	}
+
+	// what the wall is reported as when picked
+	pub fn pick(mut self, pick: Pick) -> Self {
+		self.pick = pick;
+		self
+	}
+
+	// texel of the wall column drawn at screen row y, with any decals over it drawn in the order
+	// they were added
+	pub fn texel(&self, y: i32) -> P {
+		let tex_y = (((y - self.y_top) * self.tex_step).to_i32() + self.tex_offset).clamp(0, self.tex_max);
+		let texel = self.texture[(tex_y % consts::TEXTURE_HEIGHT as i32) as usize >> self.level];
+
+		// the bottom row of the wall is at the floor
+		self.decals.iter().fold(texel, |texel, decal| {
+			decal.texel(self.tex_max - tex_y, self.level).map_or(texel, |decal| decal.over(texel))
+		})
 	}
 }
 
 pub struct TextureMap {
 	texture_width: usize,
 	texture_height: usize,
-	texture_size: usize,
-	textures: Vec<Colour>
+	textures: Vec<Colour>,
+	animations: Vec<Animation>,
+	frames: Vec<u32>,
+	mipmaps: Vec<Vec<Colour>>,
+	indexed: Vec<Vec<Indexed>>,
+	palette: Option<Palette>, // that the textures were indexed with, once their colours are dropped
+	len: usize,               // number of textures, which outlasts their colours
 }
 
 impl TextureMap {
 	pub fn new(texture_width: usize, texture_height: usize, channels: Vec<u8>) -> TextureMap {
 		let texture_size = texture_width * texture_height;
 		
-		let mut textures = Vec::new();
-		textures.reserve(channels.len() / 4);
+		let mut textures = Vec::with_capacity(channels.len() / 4);
 		
 		for i in (0..channels.len()).step_by(4) {
 			textures.push(Colour::new(channels[i], channels[i + 1], channels[i + 2], channels[i + 3]));
 		}
 
-		TextureMap { texture_width, texture_height, texture_size, textures }
+		let len = textures.len().checked_div(texture_size).unwrap_or(0);
+		TextureMap { texture_width, texture_height, textures, animations: vec![], frames: vec![], mipmaps: vec![], indexed: vec![], palette: None, len }
 	}
 
 	pub fn empty() -> TextureMap {
-		TextureMap { texture_width: 0, texture_height: 0, texture_size: 0, textures: vec![] }
+		TextureMap { texture_width: 0, texture_height: 0, textures: vec![], animations: vec![], frames: vec![], mipmaps: vec![], indexed: vec![], palette: None, len: 0 }
+	}
+
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn texture_width(&self) -> usize {
+		self.texture_width
+	}
+
+	pub fn texture_height(&self) -> usize {
+		self.texture_height
+	}
+
+	pub fn add_animation(&mut self, animation: Animation) -> Result<(), &'static str> {
+		if animation.frames().iter().any(|frame| *frame as usize >= self.len()) {
+			return Err("Animation frames must be textures in the texture map");
+		}
+
+		self.animations.push(animation);
+		Ok(())
+	}
+
+	// notes the frame each animation is showing at time, in milliseconds
+	pub fn animate(&mut self, time: u32) {
+		self.frames.clear();
+		self.frames.extend(self.animations.iter().map(|animation| animation.frame_at(time)));
+	}
+
+	// texture to draw for a surface, which is the current frame of its animation if it has one.
+	// Animations are numbered in the order they were added, and unknown ones show the texture
+	pub fn frame(&self, texture: u32, animation: Option<u32>) -> u32 {
+		animation.and_then(|animation| self.frames.get(animation as usize)).copied().unwrap_or(texture)
+	}
+
+	// builds successively smaller copies of every texture, each half the size of the one
+	// before, down to a single texel. Textures must still have their colours, so not be indexed
+	pub fn generate_mipmaps(&mut self) {
+		self.mipmaps.clear();
+
+		let mut width  = self.texture_width;
+		let mut height = self.texture_height;
+
+		while width > 1 && height > 1 {
+			let previous = self.mipmaps.last().unwrap_or(&self.textures);
+			let size     = (width >> 1) * (height >> 1);
+
+			let mut level = Vec::with_capacity(self.len() * size);
+			for code in 0..self.len() {
+				let texture = &previous[code * width * height..(code + 1) * width * height];
+				for column in 0..(width >> 1) {
+					for row in 0..(height >> 1) {
+						let texels = [(0, 0), (0, 1), (1, 0), (1, 1)].map(|(dx, dy)| texture[(2 * column + dx) * height + 2 * row + dy]);
+						let mean   = |channel: fn(&Colour) -> u8| (texels.iter().map(|texel| channel(texel) as u32).sum::<u32>() / 4) as u8;
+						level.push(Colour::new(mean(|c| c.r), mean(|c| c.g), mean(|c| c.b), mean(|c| c.a)));
+					}
+				}
+			}
+
+			self.mipmaps.push(level);
+			width  >>= 1;
+			height >>= 1;
+		}
+	}
+
+	// number of mip levels below the full size textures
+	pub fn mip_levels(&self) -> usize {
+		self.mipmaps.len().max(self.indexed.len().saturating_sub(1))
 	}
 
 	pub fn get(&self, code: u32, column: i32, flipped: bool) -> &[Colour] {
+		self.get_level(code, column, flipped, 0)
+	}
+
+	// colour of a single texel, which once the textures are indexed is that of its palette index
+	pub fn texel(&self, code: u32, x: i32, y: i32) -> Colour {
+		match &self.palette {
+			Some(palette) => palette.colour(self.get_indexed(code, x, false, 0)[y as usize]),
+			None          => self.get(code, x, false)[y as usize],
+		}
+	}
+
+	// column of a texture at a mip level, where the column is that of the full size texture.
+	// Only textures which haven't been indexed have their colours
+	pub fn get_level(&self, code: u32, column: i32, flipped: bool, level: usize) -> &[Colour] {
+		let span = self.span(code, column, flipped, level);
+		if level == 0 { &self.textures[span] } else { &self.mipmaps[level - 1][span] }
+	}
+
+	// as get_level, but the palette indices prepared by index
+	pub fn get_indexed(&self, code: u32, column: i32, flipped: bool, level: usize) -> &[Indexed] {
+		&self.indexed[level][self.span(code, column, flipped, level)]
+	}
+
+	// stores every texture and mip level as indices of the nearest colours in the palette, in place
+	// of their colours, so they take a quarter of the memory. Textures tend to share colours, so
+	// each distinct colour is only looked up once
+	pub fn index(&mut self, palette: &Palette) {
+		let mut nearest: HashMap<(u8, u8, u8, u8), Indexed> = HashMap::new();
+		self.indexed = std::iter::once(&self.textures).chain(self.mipmaps.iter()).map(|level| {
+			level.iter().map(|colour| *nearest.entry(colour.tuple()).or_insert_with(|| palette.nearest(*colour))).collect()
+		}).collect();
+
+		self.textures = vec![];
+		self.mipmaps  = vec![];
+		self.palette  = Some(palette.clone());
+	}
+
+	// where a column of a texture lies within a mip level
+	fn span(&self, code: u32, column: i32, flipped: bool, level: usize) -> std::ops::Range<usize> {
 		let column = if flipped { self.texture_width - 1 - column as usize } else { column as usize };
-		let head: usize = (self.texture_size * code as usize + column as usize * self.texture_width) as usize;
-		let tail: usize = head + self.texture_height;
-		&self.textures[head..tail]
+		let height = self.texture_height >> level;
+		let head   = (self.texture_width >> level) * height * code as usize + (column >> level) * height;
+		head..head + height
 	}
 }
 
@@ -143,125 +396,507 @@ impl TryFrom<&serde_json::Value> for TextureMap {
 		let height   = json["height"].as_u64().unwrap() as usize;
 		let byte_str = json["textures"].as_str().unwrap();
 		let bytes: Vec<u8> = general_purpose::STANDARD_NO_PAD.decode(byte_str).expect("failed to decode textures");
-		Ok(TextureMap::new(width, height, bytes))
+		let mut texture_map = TextureMap::new(width, height, bytes);
+
+		// animations are optional, each cycles through its own list of textures and is referred to
+		// by tiles and sprites by its position in the list
+		if let Some(animations) = json["animations"].as_array() {
+			for animation in animations {
+				texture_map.add_animation(Animation::try_from(animation)?)?;
+			}
+		}
+
+		Ok(texture_map)
 	}
 }
 
 pub struct Renderer {
+	config: RenderConfig,
+	viewport: Viewport,
+	lighting: Lighting,
 	textures: TextureMap,
+	rows: RowDistances,
+	spans: SurfaceSpans,
+	palettized: Option<Palettized>,
+	seen: SeenCells,
+	lit: LitCells,
+	picks: Vec<Pick>,
+}
+
+// what is needed to draw palette indices rather than colours. Effects are drawn by switching to
+// another palette, the indices drawn stay the same
+struct Palettized {
+	palettes: Vec<Palette>,
+	current: usize,
+	colormaps: Colormaps,
+	ceiling: Indexed,
+	floor: Indexed,
+}
+
+// fetches and shades texels for drawing the given kind of pixel
+trait Shader<P: Pixel> {
+	fn texture(&self, code: u32, column: i32, flipped: bool, level: usize) -> &[P];
+	fn shade(&self, texel: P, shade: Shade) -> P;
+
+	// flat ceiling and floor drawn where there is no surface
+	fn background(&self) -> (P, P);
+
+	// copies a drawn pixel into the frame buffer
+	fn put<B: FrameBuffer>(&self, buf: &mut B, x: i32, y: i32, pixel: P);
+}
+
+impl Shader<Colour> for Renderer {
+	fn texture(&self, code: u32, column: i32, flipped: bool, level: usize) -> &[Colour] {
+		self.textures.get_level(code, column, flipped, level)
+	}
+
+	fn shade(&self, texel: Colour, shade: Shade) -> Colour {
+		self.lighting.apply(texel, shade)
+	}
+
+	fn background(&self) -> (Colour, Colour) {
+		(self.config.ceiling_colour, self.config.floor_colour)
+	}
+
+	fn put<B: FrameBuffer>(&self, buf: &mut B, x: i32, y: i32, pixel: Colour) {
+		buf.set(x, y, pixel);
+	}
+}
+
+// only used while the renderer has palettes, see Renderer::render
+impl Shader<Indexed> for Renderer {
+	fn texture(&self, code: u32, column: i32, flipped: bool, level: usize) -> &[Indexed] {
+		self.textures.get_indexed(code, column, flipped, level)
+	}
+
+	fn shade(&self, texel: Indexed, shade: Shade) -> Indexed {
+		self.palettized.as_ref().unwrap().colormaps.apply(texel, shade)
+	}
+
+	fn background(&self) -> (Indexed, Indexed) {
+		let palettized = self.palettized.as_ref().unwrap();
+		(palettized.ceiling, palettized.floor)
+	}
+
+	fn put<B: FrameBuffer>(&self, buf: &mut B, x: i32, y: i32, pixel: Indexed) {
+		let palettized = self.palettized.as_ref().unwrap();
+		buf.set_indexed(x, y, pixel, &palettized.palettes[palettized.current]);
+	}
 }
 
 impl Renderer {
-	pub fn new(textures: TextureMap) -> Renderer {
-		Renderer{ textures }
+	pub fn new(config: RenderConfig, textures: TextureMap) -> Renderer {
+		let viewport = Viewport::new(&config, trig::ANGLE_60);
+		let lighting = Lighting::new(config.fog.as_ref());
+
+		let mut textures = textures;
+		if config.mipmaps { textures.generate_mipmaps(); }
+
+		let rows  = RowDistances::new(&viewport, consts::PLAYER_HEIGHT);
+		let spans = SurfaceSpans::new(&Camera::default(), &viewport, &rows);
+
+		// with palettes, textures are drawn as indices of the first and shaded with lookup tables
+		let palettized = config.palettes.first().map(|palette| {
+			textures.index(palette);
+			Palettized {
+				palettes: config.palettes.clone(),
+				current: 0,
+				colormaps: Colormaps::new(palette, config.fog.as_ref()),
+				ceiling: palette.nearest(config.ceiling_colour),
+				floor: palette.nearest(config.floor_colour),
+			}
+		});
+
+		Renderer{ config, viewport, lighting, textures, rows, spans, palettized, seen: SeenCells::new(0, 0), lit: LitCells::default(), picks: vec![] }
 	}
 
-	pub fn render_column(&self, buf: &mut[u8], origin_x: i32, origin_y: i32, angle: i32, column: i32, camera: &Camera, scene: &Scene) {
-			
-		let parameters = self.intersect_to_render_params(origin_x, origin_y, angle, column, camera, scene);
+	pub fn config(&self) -> &RenderConfig {
+		&self.config
+	}
+
+	// the textures the scene is drawn with, also available to draw over the display
+	pub fn textures(&self) -> &TextureMap {
+		&self.textures
+	}
+
+	// what was drawn at a pixel of the last frame, which is only recorded when picking is enabled
+	pub fn pick(&self, x: i32, y: i32) -> Pick {
+		if x < 0 || x >= self.viewport.width() || y < 0 || y >= self.viewport.height() { return Pick::Nothing; }
+		self.picks.get((x * self.viewport.height() + y) as usize).copied().unwrap_or(Pick::Nothing)
+	}
+
+	// cells of the scene which have been drawn, for the automap
+	pub fn seen(&self) -> &SeenCells {
+		&self.seen
+	}
+
+	pub fn seen_mut(&mut self) -> &mut SeenCells {
+		&mut self.seen
+	}
+
+	// number of palettes, zero when drawing in true colour
+	pub fn palette_count(&self) -> usize {
+		self.config.palettes.len()
+	}
+
+	// shows every following frame with another palette, such as one tinted red when the player is
+	// hurt, or the first palette to return to normal. Returns false if there is no such palette
+	pub fn set_palette(&mut self, index: usize) -> bool {
+		match self.palettized.as_mut() {
+			Some(palettized) if index < palettized.palettes.len() => { palettized.current = index; true },
+			_ => false,
+		}
+	}
+
+	// palette the display is shown with, which is what turns the indices drawn into an
+	// IndexedBuffer into colours. None when drawing in true colour
+	pub fn palette(&self) -> Option<&Palette> {
+		self.palettized.as_ref().map(|palettized| &palettized.palettes[palettized.current])
+	}
+
+	// draws a single column of the display into buf, which holds just that column from top to
+	// bottom, and records the distance to the wall which covers each row of the column in depth.
+	// The floor and ceiling distances are those prepared by render for the camera's eye height
+	fn render_column<P: Pixel>(&self, buf: &mut[P], (depth, picks): (&mut[i32], &mut[Pick]), column: i32, camera: &Camera, scene: &Scene, time: u32) where Self: Shader<P> {
+		let angle    = (camera.angle() + self.viewport.ray_angle(column)).rem_euclid(trig::ANGLE_360);
+		let horizon  = self.horizon(camera);
+		let sky      = self.sky_column(scene, angle);
+		let surfaces = SurfaceCaster::new(column, &self.viewport, &self.rows, &self.spans, scene);
 
-		let y_min = parameters[0].y_min;
-		let y_max = parameters[0].y_max;
+		let parameters = self.intersect_to_render_params(angle, column, camera, scene, time);
+
+		self.render_background(buf, horizon);
+
+		// walls can be of different heights, so the rows to draw them in span from the top of the
+		// tallest to the bottom of the nearest. If the ray left the scene without hitting anything
+		// there is only floor and ceiling to draw
+		let y_min = parameters.iter().map(|intersect| intersect.y_min).fold(horizon, std::cmp::min);
+		let y_max = parameters.iter().map(|intersect| intersect.y_max).fold(horizon, std::cmp::max);
 
 		// draw ceiling
+		let picking = self.config.picking;
 		for y in 0..y_min {
-			let intersect = raycast::find_ceiling_intersection(origin_x, origin_y, angle, y, column, scene);
-			put_surface_pixel!(intersect, buf, screen_idx!(column, y), self.textures);
+			let idx = y as usize;
+			let ceiling = surfaces.ceiling(horizon - y);
+			if picking { picks[idx] = Pick::surface(&ceiling, sky.is_some()); }
+
+			match (ceiling, sky) {
+				(None, Some(sky)) => { buf[idx] = sky[self.sky_row(y, horizon)]; },
+				(intersect, _)    => { put_surface_pixel!(intersect, buf, idx, self.surface_mip_level(consts::WALL_HEIGHT - camera.height(), horizon - y), self, scene, time); },
+			}
 		}
 
-		// draw walls		
+		// draw walls, noting how many of the walls along the ray could be seen
+		let mut reached = 0;
 		for y in y_min..=y_max {
-			let mut pixel = Colour::new(0, 0, 0, 0);
+			let mut pixel = P::clear();
+			let mut pick = Pick::Nothing;
 			
-			let idx: usize = screen_idx!(column, y);
+			let idx: usize = y as usize;
 			
-			for intersect in parameters.iter() {
-				if y < intersect.y_min || y > intersect.y_max { break; } // terminate early if either we're above/below the tallest wall
-				if pixel.a >= 255 { break; }                             // or the pixel is solid
-				let tex_y = intersect.tex_idx[y as usize];
-				pixel = pixel.blend(&intersect.texture[tex_y]);
+			for (i, intersect) in parameters.iter().enumerate() {
+				if pixel.is_opaque() { break; }                             // terminate early if the pixel is solid
+				if y < intersect.y_min || y > intersect.y_max { continue; } // a taller wall may be behind a shorter one
+				reached = std::cmp::max(reached, i + 1);
+
+				// the pick is the nearest wall not seen through at this pixel
+				let texel = self.shade(intersect.texel(y), intersect.shade);
+				if pick == Pick::Nothing && !texel.is_clear() { pick = intersect.pick; }
+				pixel = pixel.over(texel);
+
+				// sprites further away are hidden once the walls have covered the pixel, not where
+				// they could still be seen through a clear part of a nearer one
+				if pixel.is_opaque() { depth[idx] = intersect.dist; }
 			}
 			
 			// blend in the floor or ceiling through transparent areas if necessary
-			if pixel.a < 255 {
-				let intersect = if y > camera.horizon() {
-					raycast::find_floor_intersection(origin_x, origin_y, angle, y, column, scene)
+			if !pixel.is_opaque() {
+				if y > horizon {
+					let intersect = surfaces.floor(y - horizon);
+					if pick == Pick::Nothing { pick = Pick::surface(&intersect, false); }
+					pixel = blend_surface_pixel!(intersect, pixel, self.surface_mip_level(camera.height(), y - horizon), self, scene, time);
 				} else {
-					raycast::find_ceiling_intersection(origin_x, origin_y, angle, y, column, scene)
-				};
+					let ceiling = surfaces.ceiling(horizon - y);
+					if pick == Pick::Nothing { pick = Pick::surface(&ceiling, sky.is_some()); }
 
-				pixel = blend_surface_pixel!(intersect, pixel, self.textures);
+					match (ceiling, sky) {
+						(None, Some(sky)) => pixel = pixel.over(sky[self.sky_row(y, horizon)]),
+						(intersect, _)    => pixel = blend_surface_pixel!(intersect, pixel, self.surface_mip_level(consts::WALL_HEIGHT - camera.height(), horizon - y), self, scene, time),
+					}
+				}
 			}
 
-			blend_colour_to_buf!(pixel, buf, idx);
+			buf[idx] = pixel.over(buf[idx]);
+			if picking { picks[idx] = pick; }
 		}
 
+		// the automap shows the cells the ray crossed up to the furthest wall seen. Distances to
+		// walls are along the centre ray, so undo the correction to get the length of this one
+		let seen_distance = reached.checked_sub(1).map_or(consts::FP_MAX_RAY_LENGTH, |last| fp::mul(parameters[last].dist, self.viewport.fisheye_correction(column)));
+		self.seen.mark_ray(camera.x().to_fp(), camera.y().to_fp(), angle, seen_distance);
+
 		// draw floor
-		for y in y_max..consts::PROJECTION_PLANE_HEIGHT {
-			let intersect = raycast::find_floor_intersection(origin_x, origin_y, angle, y, column, scene);
-			put_surface_pixel!(intersect, buf, screen_idx!(column, y), self.textures);
+		for y in (y_max + 1)..self.viewport.height() {
+			let intersect = surfaces.floor(y - horizon);
+			if picking { picks[y as usize] = Pick::surface(&intersect, false); }
+			put_surface_pixel!(intersect, buf, y as usize, self.surface_mip_level(camera.height(), y - horizon), self, scene, time);
 		}
 	}
 
-	pub fn render(&self, buf: &mut[u8], scene: &Scene, camera: &Camera) {
-		self.render_background(buf);
-		
-		// angle is the direction camera is facing
-		// need to start out sweep 30 degrees to the left
-		let mut angle = if camera.angle() < trig::ANGLE_30 {
-			camera.angle() - trig::ANGLE_30 + trig::ANGLE_360
+	// time is in milliseconds and drives animated textures and light effects. Only as much of the
+	// display as fits in the frame buffer is drawn, from its top left corner
+	pub fn render<B: FrameBuffer>(&mut self, buf: &mut B, scene: &Scene, camera: &Camera, time: u32) {
+		// the projection depends on the field of view, so rebuild it whenever the camera's changes
+		let fov_changed = camera.fov() != self.viewport.fov();
+		if fov_changed {
+			self.viewport = Viewport::new(&self.config, camera.fov());
+		}
+
+		// as do the distances to the floor and ceiling, which also depend on the eye height
+		if fov_changed || camera.height() != self.rows.eye_height() {
+			self.rows = RowDistances::new(&self.viewport, camera.height());
+		}
+
+		// and where they lie across the world, which changes whenever the camera moves
+		self.spans = SurfaceSpans::new(camera, &self.viewport, &self.rows);
+
+		self.textures.animate(time);
+
+		if self.seen.width() != scene.width() || self.seen.height() != scene.height() {
+			self.seen = SeenCells::new(scene.width(), scene.height());
+		}
+
+		// point lights and the walls which shadow them can move between frames
+		self.lit = scene.lit_cells();
+
+		// palettes only change the pixels drawn, everything else is shared
+		self.picks = if self.palettized.is_some() {
+			self.draw::<Indexed, B>(buf, scene, camera, time)
 		} else {
-			camera.angle() - trig::ANGLE_30
+			self.draw::<Colour, B>(buf, scene, camera, time)
 		};
+	}
+
+	// returns what was drawn at each pixel, if picking is enabled
+	fn draw<P: Pixel, B: FrameBuffer>(&self, buf: &mut B, scene: &Scene, camera: &Camera, time: u32) -> Vec<Pick> where Self: Shader<P> {
+		// columns are drawn into a scratch buffer a column at a time, so each has its own slice
+		// and they can be drawn independently of each other, then copied into the display's rows.
+		// The distance to the nearest wall drawn at each pixel, used to hide sprites behind walls,
+		// is stored the same way
+		let width       = self.viewport.width() as usize;
+		let height      = self.viewport.height() as usize;
+		let mut columns = vec![P::clear(); width * height];
+		let mut depth   = vec![consts::FP_MAX_RAY_LENGTH; width * height];
 
-		// ray casting uses fixed point notation, so convert camera coordinates to fixed point
-		let origin_x = camera.x().to_fp();
-		let origin_y = camera.y().to_fp();
+		// as is what was drawn at each pixel, though without picking each column has a single unused entry
+		let pick_rows   = if self.config.picking { height } else { 1 };
+		let mut picks   = vec![Pick::Nothing; width * pick_rows];
 
-		// sweep of the rays will be through 60 degrees
-		for sweep in 0..trig::ANGLE_60 {
+		#[cfg(feature = "parallel")]
+		self.render_columns_parallel((&mut columns, &mut depth, &mut picks), camera, scene, time);
 
-			self.render_column(buf, origin_x, origin_y, angle, sweep, &camera, &scene);
+		#[cfg(not(feature = "parallel"))]
+		self.render_columns((&mut columns, &mut depth, &mut picks), camera, scene, time);
 
-			angle += 1;
-			if angle >= trig::ANGLE_360 {
-				angle -= trig::ANGLE_360;
+		self.render_sprites(&mut columns, &depth, &mut picks, scene, camera, time);
+
+		for (column, pixels) in columns.chunks(height).take(buf.width().max(0) as usize).enumerate() {
+			for (y, pixel) in pixels.iter().take(buf.height().max(0) as usize).enumerate() {
+				self.put(buf, column as i32, y as i32, *pixel);
 			}
 		}
+
+		if self.config.picking { picks } else { vec![] }
+	}
+
+	// draws every column into the scratch buffers prepared by draw, each casting a ray at its own
+	// angle relative to the direction the camera faces. With the parallel feature it is only kept
+	// to check render_columns_parallel against
+	#[cfg(any(test, not(feature = "parallel")))]
+	fn render_columns<P: Pixel>(&self, (columns, depth, picks): (&mut [P], &mut [i32], &mut [Pick]), camera: &Camera, scene: &Scene, time: u32) where Self: Shader<P> {
+		let height    = self.viewport.height() as usize;
+		let pick_rows = picks.len() / self.viewport.width() as usize;
+
+		columns.chunks_mut(height).zip(depth.chunks_mut(height)).zip(picks.chunks_mut(pick_rows)).enumerate().for_each(|(column, ((buf, depth), picks))| {
+			self.render_column(buf, (depth, picks), column as i32, camera, scene, time);
+		});
 	}
 
-	fn render_background(&self, buf: &mut[u8]) {
-		let ceiling = Colour::new(0x38, 0x38,  0x38, 0xFF);
-		let floor   = Colour::new(0x70, 0x70,  0x70, 0xFF);
+	// as render_columns, spread across threads. The columns don't share anything they write to, so
+	// the result is the same
+	#[cfg(feature = "parallel")]
+	fn render_columns_parallel<P: Pixel>(&self, (columns, depth, picks): (&mut [P], &mut [i32], &mut [Pick]), camera: &Camera, scene: &Scene, time: u32) where Self: Shader<P> {
+		let height    = self.viewport.height() as usize;
+		let pick_rows = picks.len() / self.viewport.width() as usize;
 
-		for y in 0..consts::PROJECTION_PLANE_HORIZON {
-			for x in 0..consts::PROJECTION_PLANE_WIDTH {
-				colour_to_buf!(ceiling, buf, screen_idx!(x, y));
+		columns.par_chunks_mut(height).zip(depth.par_chunks_mut(height)).zip(picks.par_chunks_mut(pick_rows)).enumerate().for_each(|(column, ((buf, depth), picks))| {
+			self.render_column(buf, (depth, picks), column as i32, camera, scene, time);
+		});
+	}
+
+	// mip level to sample a texture when this many texels fall within a pixel, the level whose
+	// texels are closest to a pixel in size without being smaller
+	fn mip_level(&self, texels_per_pixel: i32) -> usize {
+		if !self.config.mipmaps || texels_per_pixel <= 1 { return 0; }
+		(texels_per_pixel.ilog2() as usize).min(self.textures.mip_levels())
+	}
+
+	// mip level for a wall or sprite drawn at the given size on screen
+	fn wall_mip_level(&self, size: i32) -> usize {
+		self.mip_level(consts::TEXTURE_HEIGHT as i32 / size.max(1))
+	}
+
+	// mip level for a floor or ceiling some rows from the horizon, where height is the distance
+	// between the eye and the surface. Towards the horizon each row covers much more of the
+	// surface in depth than across, so that decides the level
+	fn surface_mip_level(&self, height: i32, row: i32) -> usize {
+		if !self.config.mipmaps { return 0; }
+		let row = row.max(1);
+		self.mip_level(self.viewport.distance_to_projection_plane() * height / (row * row))
+	}
+
+	// the camera may look further up or down than the display can show, so keep the horizon on screen
+	fn horizon(&self, camera: &Camera) -> i32 {
+		camera.horizon().clamp(0, self.viewport.height() - 1)
+	}
+
+	// column of the sky panorama seen at the given angle, if the scene has a sky. The panorama
+	// wraps once around the full circle so it scrolls as the camera turns
+	fn sky_column<P: Pixel>(&self, scene: &Scene, angle: i32) -> Option<&[P]> where Self: Shader<P> {
+		let sky = scene.sky();
+		if sky.is_empty() { return None; }
+
+		let columns = (sky.len() * consts::TEXTURE_WIDTH) as i64;
+		let column  = (angle as i64 * columns / trig::ANGLE_360 as i64) as usize;
+		Some(self.texture(sky[column / consts::TEXTURE_WIDTH], (column % consts::TEXTURE_WIDTH) as i32, false, 0))
+	}
+
+	// row of the sky texture drawn at screen row y. The bottom of the sky sits on the horizon and
+	// its top half the display above it, beyond which the top row of the texture is repeated
+	fn sky_row(&self, y: i32, horizon: i32) -> usize {
+		let texture_height = consts::TEXTURE_HEIGHT as i32;
+		let rows = (horizon - y) * 2 * texture_height / self.viewport.height();
+		(texture_height - 1 - rows).clamp(0, texture_height - 1) as usize
+	}
+
+	// fills a single column with the flat ceiling and floor colours
+	fn render_background<P: Pixel>(&self, buf: &mut[P], horizon: i32) where Self: Shader<P> {
+		let (ceiling, floor) = self.background();
+		buf[..horizon as usize].fill(ceiling);
+		buf[horizon as usize..].fill(floor);
+	}
+
+	// draws sprites over the columns drawn by render_column, which are stored the same way as depth
+	fn render_sprites<P: Pixel>(&self, columns: &mut [P], depth: &[i32], picks: &mut [Pick], scene: &Scene, camera: &Camera, time: u32) where Self: Shader<P> {
+		let sin      = trig::sin(camera.angle());
+		let cos      = trig::cos(camera.angle());
+		let width    = self.viewport.width();
+		let height   = self.viewport.height();
+		let distance = self.viewport.distance_to_projection_plane();
+		let horizon  = self.horizon(camera);
+
+		// tangent of half the field of view
+		let half_fov = fp::div((width >> 1).to_fp(), distance.to_fp());
+
+		// rotate each sprite into camera space, where forward is the distance along the
+		// viewing direction and side is the offset to the right of it. Sprites behind the
+		// camera or too far to either side to be seen are dropped
+		let mut visible: Vec<(i32, i32, usize, &Sprite)> = scene.sprites().iter().enumerate().filter_map(|(index, sprite)| {
+			let dx      = (sprite.x - camera.x()).to_fp();
+			let dy      = (sprite.y - camera.y()).to_fp();
+			let forward = fp::add(fp::mul(dx, cos), fp::mul(dy, sin));
+			let side    = fp::sub(fp::mul(dy, cos), fp::mul(dx, sin));
+
+			if forward < 1.to_fp() || fp::sub(side.abs(), consts::FP_TILE_SIZE >> 1) > fp::mul(forward, half_fov) {
+				None
+			} else {
+				Some((forward, side, index, sprite))
 			}
-		}
+		}).collect();
+
+		// draw back to front so nearer sprites blend over those behind them
+		visible.sort_by_key(|&(forward, _, _, _)| std::cmp::Reverse(forward));
+
+		for (forward, side, index, sprite) in visible {
+			// fog is worked out from how far away the sprite actually is, as it is for walls and floors
+			let actual  = ((sprite.x - camera.x()) as f64).hypot((sprite.y - camera.y()) as f64);
+			let size    = self.viewport.wall_height(forward.to_i32());
+			let centre  = (width >> 1) + (side as i64 * distance as i64 / forward as i64) as i32;
+			let x_start = centre - (size >> 1);
+			let x_min   = std::cmp::max(0, x_start);
+			let x_max   = std::cmp::min(width, x_start + size);
+			let y_top   = horizon - size * (consts::WALL_HEIGHT - camera.height()) / consts::WALL_HEIGHT;
+			let y_min   = std::cmp::max(0, y_top);
+			let y_max   = std::cmp::min(height - 1, horizon + size * camera.height() / consts::WALL_HEIGHT);
+			let light   = scene.light_level(sprite.x / consts::TILE_SIZE, sprite.y / consts::TILE_SIZE, time);
+			let shade   = self.lighting.shade_at(actual.to_fp(), light).glow(scene.glow_at(sprite.x, sprite.y));
+			let level   = self.wall_mip_level(size);
+			let frame   = self.textures.frame(sprite.texture, sprite.animation);
 
-		for y in consts::PROJECTION_PLANE_HORIZON..consts::PROJECTION_PLANE_HEIGHT {
-			for x in 0..consts::PROJECTION_PLANE_WIDTH {
-				colour_to_buf!(floor, buf, screen_idx!(x, y));
+			for x in x_min..x_max {
+				let column     = (x - x_start) * consts::TEXTURE_WIDTH as i32 / size;
+				let texture    = self.texture(frame, column, false, level);
+				let parameters = RenderParameters::new(texture, shade, self.viewport.wall_texture_step(size), forward, y_top, y_min, y_max).mip(level);
+
+				for y in y_min..=y_max {
+					let idx = (x * height + y) as usize;
+					if depth[idx] <= forward { continue; } // sprite is hidden behind a wall at this pixel
+
+					let texel = self.shade(parameters.texel(y), parameters.shade);
+					if self.config.picking && !texel.is_clear() { picks[idx] = Pick::Sprite(index); }
+					columns[idx] = texel.over(columns[idx]);
+				}
 			}
 		}
 	}
 
-	fn intersect_to_render_params(&self, origin_x: i32, origin_y: i32, angle: i32, column: i32, camera: &Camera, scene: &Scene) -> Vec<RenderParameters> {
-		let intersects = raycast::find_wall_intersections(origin_x, origin_y, angle, column, scene);
+	fn intersect_to_render_params<P: Pixel>(&self, angle: i32, column: i32, camera: &Camera, scene: &Scene, time: u32) -> Vec<RenderParameters<'_, P>> where Self: Shader<P> {
+		let intersects = raycast::find_wall_intersections(camera.x().to_fp(), camera.y().to_fp(), angle, column, &self.viewport, scene);
+		let horizon    = self.horizon(camera);
+
+		// walls are fogged by their distance along the ray rather than straight ahead, as floors
+		// and ceilings are, so the fog has no seam where they meet
+		let fisheye    = self.viewport.fisheye_correction(column);
 
 		// for each intersection, get a reference to its texture and figure out how
 		// it should be drawn
-		return intersects.iter().map(|intersect| {
+		intersects.iter().map(|intersect| {
 			let dist        = intersect.dist;
-			let wall_height = trig::wall_height(dist);
-			let mid_height  = wall_height >> 1;
-			let y_min       = std::cmp::max(0, camera.horizon() - mid_height);
-			let y_max       = std::cmp::min(consts::PROJECTION_PLANE_HEIGHT - 1, camera.horizon() + mid_height);
-			let tex_idx     = trig::wall_texture_index(wall_height);
-			let texture     = self.textures.get(intersect.texture, intersect.texture_column, intersect.reverse);
-			RenderParameters::new(texture, tex_idx, y_min, y_max)
-		}).collect();
+			let wall_height = self.viewport.wall_height(dist.to_i32());
+
+			// walls stand on the floor, so their bottom is as far below the horizon as the eye is
+			// above the floor and their top depends on how tall they are
+			let y_top       = horizon - wall_height * (intersect.height - camera.height()) / consts::WALL_HEIGHT;
+			let y_min       = std::cmp::max(0, y_top);
+			let y_max       = std::cmp::min(self.viewport.height() - 1, horizon + wall_height * camera.height() / consts::WALL_HEIGHT);
+			let tex_step    = self.viewport.wall_texture_step(wall_height);
+			let level       = self.wall_mip_level(wall_height);
+			let texture     = self.texture(self.textures.frame(intersect.texture, intersect.animation), intersect.texture_column, intersect.reverse, level);
+			let light       = scene.light_level(intersect.cell_x, intersect.cell_y, time);
+			let light       = self.config.face_shading.map_or(light, |shading| shading.apply(light, intersect.face));
+			let normal      = intersect.face.normal();
+			let glow        = scene.glow_at(intersect.x + normal.0, intersect.y + normal.1);
+			let shade       = self.lighting.shade_at(fp::mul(dist, fisheye), light).glow(glow);
+			let pick        = Pick::Wall { x: intersect.tile_x, y: intersect.tile_y, face: intersect.face };
+			let mut params  = RenderParameters::new(texture, shade, tex_step, dist, y_top, y_min, y_max).tile(intersect.height).mip(level).pick(pick);
+
+			// decals are placed along the face the same way as the wall's texture, and so are
+			// flipped along with it
+			params.decals = scene.decals(intersect.tile_x, intersect.tile_y, intersect.face).iter()
+				.filter_map(|decal| self.decal_column(decal, intersect, level))
+				.collect();
+			params
+		}).collect()
+	}
+
+	fn decal_column<P: Pixel>(&self, decal: &Decal, intersect: &Intersection, level: usize) -> Option<DecalColumn<'_, P>> where Self: Shader<P> {
+		let left = decal.x - decal.size / 2;
+		let offset = intersect.texture_column - left;
+		if offset < 0 || offset >= decal.size { return None; }
+
+		let column = offset * consts::TEXTURE_WIDTH as i32 / decal.size;
+		let texture = self.texture(decal.texture, column, intersect.reverse, level);
+		Some(DecalColumn { texture, bottom: decal.y - decal.size / 2, size: decal.size })
 	}
 }
 
@@ -269,7 +904,353 @@ impl TryFrom<&serde_json::Value> for Renderer {
 	type Error = &'static str;
 
 	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
-		let textures  = TextureMap::try_from(&json["texture_map"]).ok().unwrap();
-		Ok(Renderer::new(textures))
+		let config    = RenderConfig::try_from(json)?;
+		let textures  = TextureMap::try_from(&json["texture_map"])?;
+		Ok(Renderer::new(config, textures))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::render::{ ByteBuffer, Falloff, Fog, IndexedBuffer, PixelFormat };
+	use crate::scene::{ Light, LightEffect, TextureTile, Tile };
+	use std::fs;
+	use std::path::Path;
+
+	fn load_scene() -> Scene {
+		let fname = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("resources").join("test-scene-1.json");
+		let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(fname).unwrap()).unwrap();
+		Scene::try_from(&json).unwrap()
+	}
+
+	// 66 plain white 64x64 textures, apart from those given a colour of their own
+	fn coloured_textures(colours: &[(usize, [u8; 4])]) -> TextureMap {
+		let size = 64 * 64 * 4;
+		let mut channels = vec![255; size * 66];
+		for (texture, colour) in colours {
+			channels[size * texture..size * (texture + 1)].chunks_mut(4).for_each(|texel| texel.copy_from_slice(colour));
+		}
+		TextureMap::new(64, 64, channels)
+	}
+
+	fn render_scene(renderer: &mut Renderer, scene: &Scene, camera: &Camera) -> Vec<u8> {
+		let (width, height) = (renderer.config().width, renderer.config().height);
+		let mut bytes = vec![0; (width * height * 4) as usize];
+		renderer.render(&mut ByteBuffer::new(&mut bytes, width, height, width as usize * 4, PixelFormat::Rgba8).unwrap(), scene, camera, 0);
+		bytes
+	}
+
+	fn pixel(bytes: &[u8], width: i32, x: i32, y: i32) -> (u8, u8, u8, u8) {
+		let idx = ((x + y * width) * 4) as usize;
+		(bytes[idx], bytes[idx + 1], bytes[idx + 2], bytes[idx + 3])
+	}
+
+	#[test]
+	fn test_mipmaps_average_texels() {
+		// two 2x2 textures, stored a column at a time
+		let channels = [
+			[0, 0, 0, 255], [40, 0, 0, 255], [80, 0, 0, 255], [120, 0, 0, 255],
+			[0, 8, 0, 0], [0, 8, 0, 0], [0, 8, 0, 255], [0, 8, 0, 255],
+		].concat();
+
+		let mut textures = TextureMap::new(2, 2, channels);
+		textures.generate_mipmaps();
+
+		assert_eq!(1, textures.mip_levels());
+		assert_eq!((60, 0, 0, 255), textures.get_level(0, 1, false, 1)[0].tuple());
+		assert_eq!((0, 8, 0, 127), textures.get_level(1, 0, false, 1)[0].tuple());
+	}
+
+	#[test]
+	fn test_indexed_textures_drop_colours() {
+		let channels = [
+			[0, 0, 0, 255], [40, 0, 0, 255], [80, 0, 0, 255], [120, 0, 0, 255],
+			[0, 8, 0, 0], [0, 8, 0, 0], [0, 8, 0, 255], [0, 8, 0, 255],
+		].concat();
+
+		let mut textures = TextureMap::new(2, 2, channels);
+		textures.generate_mipmaps();
+
+		let palette = Palette::new(vec![Colour::new(0, 0, 0, 255), Colour::new(100, 0, 0, 255), Colour::new(0, 8, 0, 255)]).unwrap();
+		textures.index(&palette);
+
+		// only the indices are kept, every texture and mip level still being there
+		assert!(textures.textures.is_empty() && textures.mipmaps.is_empty());
+		assert_eq!(2, textures.len());
+		assert_eq!(1, textures.mip_levels());
+		assert_eq!(1, textures.get_indexed(0, 1, false, 1)[0].0);
+
+		// and the colours of texels drawn over the display come from the palette
+		assert_eq!((100, 0, 0, 255), textures.texel(0, 1, 1).tuple());
+		assert_eq!((0, 0, 0, 0), textures.texel(1, 0, 0).tuple());
+	}
+
+	#[test]
+	fn test_animations() {
+		let mut textures = coloured_textures(&[(10, [255, 0, 0, 255]), (11, [0, 255, 0, 255]), (12, [0, 0, 255, 255])]);
+		textures.add_animation(Animation::new(vec![10, 11], 100).unwrap()).unwrap();
+		textures.add_animation(Animation::new(vec![11, 12], 100).unwrap()).unwrap();
+
+		// animations sharing a texture keep to their own frames, and the texture itself stays put
+		textures.animate(100);
+		assert_eq!(11, textures.frame(11, Some(0)));
+		assert_eq!(12, textures.frame(11, Some(1)));
+		assert_eq!(11, textures.frame(11, None));
+		assert_eq!(4, textures.frame(4, Some(2)));
+
+		// surfaces show the frame of the animation they refer to
+		let mut scene    = load_scene();
+		let mut renderer = Renderer::new(RenderConfig::new(64, 40, 20).unwrap(), textures);
+		scene.set_x_wall(4, 2, Tile::Surface(TextureTile { animation: Some(1), ..TextureTile::new(10) }));
+		assert_eq!((0, 255, 0, 255), pixel(&render_scene(&mut renderer, &scene, &Camera::new(160, 160, trig::ANGLE_0, 20)), 64, 32, 20));
+	}
+
+	#[test]
+	fn test_pick() {
+		let scene = load_scene();
+
+		let config   = RenderConfig { picking: true, ..RenderConfig::new(64, 40, 20).unwrap() };
+		let textures = TextureMap::new(64, 64, vec![255; 64 * 64 * 4 * 66]);
+		let mut renderer = Renderer::new(config, textures);
+
+		// facing the door on the east side of the middle cell
+		let camera    = Camera::new(160, 160, trig::ANGLE_0, 20);
+		let mut bytes = vec![0; 64 * 40 * 4];
+		renderer.render(&mut ByteBuffer::new(&mut bytes, 64, 40, 64 * 4, PixelFormat::Rgba8).unwrap(), &scene, &camera, 0);
+
+		assert_eq!(Pick::Wall { x: 4, y: 2, face: Face::West }, renderer.pick(32, 20));
+		assert!(matches!(renderer.pick(32, 39), Pick::Floor { y: 2, .. }));
+		assert!(matches!(renderer.pick(32, 0), Pick::Ceiling { y: 2, .. }));
+		assert_eq!(Pick::Nothing, renderer.pick(64, 20));
+	}
+
+	#[test]
+	fn test_decals() {
+		let mut scene = load_scene();
+
+		let mut renderer = Renderer::new(RenderConfig::new(64, 40, 20).unwrap(), coloured_textures(&[(11, [255, 0, 0, 255])]));
+
+		// a decal covering the whole door on the east side of the middle cell
+		assert!(scene.add_decal(4, 2, Face::West, Decal::new(11, 32, 32, 64)));
+		assert!(!scene.add_decal(4, 2, Face::Up, Decal::new(11, 32, 32, 64)));
+
+		let camera    = Camera::new(160, 160, trig::ANGLE_0, 20);
+		let mut bytes = vec![0; 64 * 40 * 4];
+		let mut buf   = ByteBuffer::new(&mut bytes, 64, 40, 64 * 4, PixelFormat::Rgba8).unwrap();
+		renderer.render(&mut buf, &scene, &camera, 0);
+
+		let pixel = buf.get(32, 20);
+		assert!(pixel.r > 0 && pixel.g == 0);
+
+		// the oldest decal makes way for the newest
+		scene.set_max_decals(1);
+		assert!(scene.add_decal(4, 2, Face::West, Decal::new(11, 8, 8, 16)));
+		assert_eq!(1, scene.decals(4, 2, Face::West).len());
+		assert_eq!(8, scene.decals(4, 2, Face::West)[0].x);
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn test_light_level_reaches_walls() {
+		let mut scene    = load_scene();
+		let mut renderer = Renderer::new(RenderConfig::new(64, 40, 20).unwrap(), coloured_textures(&[]));
+		let camera       = Camera::new(160, 160, trig::ANGLE_0, 20);
+
+		// the door on the east side of the middle cell is lit by the cell it is seen from, which
+		// strobes between half and no brightness
+		scene.set_light(3, 2, Light::with_effect(128, 0, LightEffect::Strobe, 1000).unwrap());
+
+		let mut bytes = vec![0; 64 * 40 * 4];
+		let mut buf   = ByteBuffer::new(&mut bytes, 64, 40, 64 * 4, PixelFormat::Rgba8).unwrap();
+		renderer.render(&mut buf, &scene, &camera, 0);
+		assert_eq!((128, 128, 128, 255), buf.get(32, 20).tuple());
+
+		renderer.render(&mut buf, &scene, &camera, 500);
+		assert_eq!((0, 0, 0, 255), buf.get(32, 20).tuple());
+
+		// as does the floor of that cell in front of it
+		assert_eq!((0, 0, 0, 255), buf.get(32, 39).tuple());
+	}
+
+	#[test]
+	fn test_sprites_hidden_behind_walls() {
+		let mut scene    = load_scene();
+		let mut renderer = Renderer::new(RenderConfig::new(64, 40, 20).unwrap(), coloured_textures(&[(10, [0, 255, 0, 255]), (11, [255, 0, 0, 255])]));
+		let camera       = Camera::new(160, 160, trig::ANGLE_0, 20);
+
+		// facing the door on the east side of the middle cell, with a sprite beyond it
+		scene.add_sprite(Sprite::new(288, 160, 10));
+		assert_eq!((255, 255, 255, 255), pixel(&render_scene(&mut renderer, &scene, &camera), 64, 32, 20));
+
+		// and another in front of it
+		scene.add_sprite(Sprite::new(224, 160, 11));
+		assert_eq!((255, 0, 0, 255), pixel(&render_scene(&mut renderer, &scene, &camera), 64, 32, 20));
+	}
+
+	#[test]
+	fn test_seen_past_nearer_walls() {
+		let mut scene    = load_scene();
+		let mut renderer = Renderer::new(RenderConfig::new(64, 40, 20).unwrap(), coloured_textures(&[(10, [0, 255, 0, 255]), (11, [255, 0, 0, 255]), (12, [0, 0, 0, 0]), (13, [0, 0, 255, 255])]));
+		let camera       = Camera::new(96, 160, trig::ANGLE_0, 20);
+
+		// the tall wall at the east of the room shows above a short one in front of it
+		scene.set_x_wall(4, 2, Tile::Surface(TextureTile::new(13)));
+		scene.set_x_wall(3, 2, Tile::Surface(TextureTile { height: 16, ..TextureTile::new(11) }));
+		let bytes = render_scene(&mut renderer, &scene, &camera);
+		assert_eq!((255, 0, 0, 255), pixel(&bytes, 64, 32, 32));
+		assert_eq!((0, 0, 255, 255), pixel(&bytes, 64, 32, 20));
+
+		// and a sprite is seen through the clear parts of a wall in front of it
+		scene.set_x_wall(3, 2, Tile::Surface(TextureTile::new(12)));
+		scene.add_sprite(Sprite::new(224, 160, 10));
+		let bytes = render_scene(&mut renderer, &scene, &camera);
+		assert_eq!((0, 255, 0, 255), pixel(&bytes, 64, 32, 20));
+	}
+
+	#[test]
+	fn test_sprites_drawn_back_to_front() {
+		let mut renderer = Renderer::new(RenderConfig::new(64, 40, 20).unwrap(), coloured_textures(&[(10, [0, 255, 0, 255]), (11, [255, 0, 0, 255])]));
+		let camera       = Camera::new(96, 160, trig::ANGLE_0, 20);
+
+		// the nearer sprite covers the further one whichever order they were added in
+		for order in [[(160, 11), (224, 10)], [(224, 10), (160, 11)]] {
+			let mut scene = load_scene();
+			for (x, texture) in order {
+				scene.add_sprite(Sprite::new(x, 160, texture));
+			}
+
+			assert_eq!((255, 0, 0, 255), pixel(&render_scene(&mut renderer, &scene, &camera), 64, 32, 20));
+		}
+	}
+
+	#[test]
+	fn test_sky_follows_view() {
+		// an open scene with a sky of a red and a green texture, the bottom row of each blue
+		let mut scene = Scene::new(1, 1, vec![Tile::Empty], vec![Tile::Empty], vec![Tile::Empty], vec![Tile::Empty]).unwrap();
+		scene.set_sky(vec![10, 11]);
+
+		let size = 64 * 64 * 4;
+		let mut channels = vec![255; size * 66];
+		for (texture, colour) in [(10, [255, 0, 0, 255]), (11, [0, 255, 0, 255])] {
+			for (i, texel) in channels[size * texture..size * (texture + 1)].chunks_mut(4).enumerate() {
+				texel.copy_from_slice(if i % 64 == 63 { &[0, 0, 255, 255] } else { &colour });
+			}
+		}
+
+		let mut renderer = Renderer::new(RenderConfig::new(320, 200, 100).unwrap(), TextureMap::new(64, 64, channels));
+		let red   = (255, 0, 0, 255);
+		let green = (0, 255, 0, 255);
+		let blue  = (0, 0, 255, 255);
+
+		// the panorama goes once around, so facing the other way shows the other half
+		let bytes = render_scene(&mut renderer, &scene, &Camera::new(32, 32, trig::ANGLE_90, 100));
+		assert_eq!(red, pixel(&bytes, 320, 160, 50));
+		let bytes = render_scene(&mut renderer, &scene, &Camera::new(32, 32, trig::ANGLE_270, 100));
+		assert_eq!(green, pixel(&bytes, 320, 160, 50));
+
+		// and wraps back to the start of the panorama across the display
+		let bytes = render_scene(&mut renderer, &scene, &Camera::new(32, 32, trig::ANGLE_360 - trig::ANGLE_5, 100));
+		assert_eq!(green, pixel(&bytes, 320, 0, 50));
+		assert_eq!(green, pixel(&bytes, 320, 160, 50));
+		assert_eq!(red, pixel(&bytes, 320, 319, 50));
+
+		// with the bottom of the sky on the horizon, wherever the camera looks
+		for horizon in [100, 60, 150] {
+			let bytes = render_scene(&mut renderer, &scene, &Camera::new(32, 32, trig::ANGLE_90, horizon));
+			assert_eq!(blue, pixel(&bytes, 320, 160, horizon - 1));
+			assert_eq!(red, pixel(&bytes, 320, 160, horizon - 2));
+		}
+	}
+
+	#[cfg(feature = "parallel")]
+	#[test]
+	fn test_parallel_columns_match_serial() {
+		// a short see-through wall, so rows of some columns show several walls and the floor
+		let mut scene = load_scene();
+		scene.set_x_wall(3, 2, Tile::Surface(TextureTile { height: 16, ..TextureTile::new(11) }));
+
+		let config   = RenderConfig { picking: true, ..RenderConfig::new(320, 200, 100).unwrap() };
+		let renderer = Renderer::new(config, coloured_textures(&[(11, [255, 0, 0, 128])]));
+		let camera   = Camera::new(96, 140, trig::ANGLE_30, 100);
+
+		let size  = 320 * 200;
+		let draw  = |parallel: bool| {
+			let mut columns = vec![Colour::clear(); size];
+			let mut depth   = vec![consts::FP_MAX_RAY_LENGTH; size];
+			let mut picks   = vec![Pick::Nothing; size];
+			if parallel {
+				renderer.render_columns_parallel((&mut columns, &mut depth, &mut picks), &camera, &scene, 0);
+			} else {
+				renderer.render_columns((&mut columns, &mut depth, &mut picks), &camera, &scene, 0);
+			}
+			(columns.iter().map(|colour| colour.tuple()).collect::<Vec<_>>(), depth, picks)
+		};
+
+		assert!(draw(true) == draw(false));
+	}
+
+	#[test]
+	fn test_indexed_buffer() {
+		let mut scene = load_scene();
+		scene.set_x_wall(4, 2, Tile::Surface(TextureTile::new(10)));
+
+		let red     = Colour::new(255, 0, 0, 255);
+		let palette = Palette::new(vec![Colour::new(255, 255, 255, 255), red, Colour::new(0x38, 0x38, 0x38, 255), Colour::new(0x70, 0x70, 0x70, 255)]).unwrap();
+		let config  = RenderConfig { palettes: vec![palette.clone(), palette.tint(red, 128)], ..RenderConfig::new(64, 40, 20).unwrap() };
+		let mut renderer = Renderer::new(config, coloured_textures(&[(10, [255, 0, 0, 255])]));
+		let camera       = Camera::new(160, 160, trig::ANGLE_0, 20);
+
+		// the indices drawn are those which the palette shows as the colours drawn otherwise,
+		// including after switching palettes
+		let mut indices = vec![0; 64 * 40];
+		for current in [0, 1] {
+			renderer.set_palette(current);
+			let bytes   = render_scene(&mut renderer, &scene, &camera);
+			let showing = renderer.palette().unwrap().clone();
+			renderer.render(&mut IndexedBuffer::new(&mut indices, 64, 40, 64, &palette).unwrap(), &scene, &camera, 0);
+
+			for (i, index) in indices.iter().enumerate() {
+				assert_eq!(pixel(&bytes, 64, i as i32 % 64, i as i32 / 64), showing.colour(Indexed(*index)).tuple());
+			}
+		}
+
+		assert_eq!(1, indices[20 * 64 + 32]);
+	}
+
+	#[test]
+	fn test_buffer_smaller_than_display() {
+		let scene        = load_scene();
+		let mut renderer = Renderer::new(RenderConfig::new(64, 40, 20).unwrap(), coloured_textures(&[(0, [0, 0, 255, 255])]));
+		let camera       = Camera::new(160, 160, trig::ANGLE_0, 20);
+		let full         = render_scene(&mut renderer, &scene, &camera);
+
+		// the top left of the display is drawn, and nothing past the edges of the buffer
+		let mut bytes = vec![0; 32 * 20 * 4];
+		renderer.render(&mut ByteBuffer::new(&mut bytes, 32, 20, 32 * 4, PixelFormat::Rgba8).unwrap(), &scene, &camera, 0);
+
+		for y in 0..20 {
+			for x in 0..32 {
+				assert_eq!(pixel(&full, 64, x, y), pixel(&bytes, 32, x, y));
+			}
+		}
+	}
+
+	#[test]
+	fn test_fog_has_no_seam_between_walls_and_floor() {
+		let scene  = load_scene();
+		let fog    = Fog::new(Colour::new(0, 0, 0, 255), 0, 512, Falloff::Linear).unwrap();
+		let config = RenderConfig { fog: Some(fog), picking: true, ..RenderConfig::new(320, 200, 100).unwrap() };
+		let mut renderer = Renderer::new(config, coloured_textures(&[]));
+
+		// with a wide field of view the edges of the display look well away from straight ahead
+		let mut camera = Camera::new(32, 160, trig::ANGLE_0, 100);
+		camera.set_fov(trig::ANGLE_90);
+		let bytes = render_scene(&mut renderer, &scene, &camera);
+
+		// the floor meeting the bottom of the wall is about as far away, so about as foggy
+		let bottom = (0..200).rev().find(|y| matches!(renderer.pick(0, *y), Pick::Wall { .. })).unwrap();
+		let wall   = pixel(&bytes, 320, 0, bottom).0 as i32;
+		let floor  = pixel(&bytes, 320, 0, bottom + 1).0 as i32;
+		assert!((wall - floor).abs() <= 8);
+	}
+}
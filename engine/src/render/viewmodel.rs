@@ -0,0 +1,110 @@
+use crate::render::{ Animation, FrameBuffer, Overlay, Rect, TextureMap };
+use crate::scene::texture_id;
+use crate::trig;
+use serde_json;
+use shared::fp;
+use shared::fp::{ ToFixedPoint, FromFixedPoint };
+
+// time taken to sway from one side and back again, in milliseconds
+const BOB_PERIOD: u32 = 800;
+
+// the weapon or other item the player holds, drawn at the bottom of the display over the scene.
+// It sways as the player moves, and plays through its firing frames once each time it is fired
+pub struct Viewmodel {
+	texture: u32,
+	firing: Option<Animation>,
+	scale: i32,
+	pub bob: i32,        // pixels the item sways by when moving at full speed
+	pub full_speed: i32, // movement speed, in world units a second, at which the sway is greatest
+	fired_at: Option<u32>,
+}
+
+impl Viewmodel {
+	pub fn new(texture: u32, firing: Option<Animation>, scale: i32) -> Result<Viewmodel, &'static str> {
+		if scale <= 0 {
+			return Err("Viewmodel scale must be a positive value");
+		}
+
+		Ok(Viewmodel { texture, firing, scale, bob: 16, full_speed: 480, fired_at: None })
+	}
+
+	// starts the firing frames at time in milliseconds, unless they are already playing
+	pub fn fire(&mut self, time: u32) -> bool {
+		if self.firing.is_none() || self.is_firing(time) { return false; }
+		self.fired_at = Some(time);
+		true
+	}
+
+	pub fn is_firing(&self, time: u32) -> bool {
+		match (&self.firing, self.fired_at) {
+			(Some(firing), Some(fired_at)) => time.wrapping_sub(fired_at) < firing.length(),
+			_ => false,
+		}
+	}
+
+	// texture shown at time, which is the first texture unless firing
+	pub fn frame(&self, time: u32) -> u32 {
+		match (&self.firing, self.fired_at) {
+			(Some(firing), Some(fired_at)) if self.is_firing(time) => firing.frame_at(time - fired_at),
+			_ => self.texture,
+		}
+	}
+
+	// draws the item centred along the bottom of the display after the scene has been rendered.
+	// The speed the player is moving at, in world units a second, sets how far it sways: from side
+	// to side and dipping at either end, as if carried by someone walking
+	pub fn render<B: FrameBuffer>(&self, buf: &mut B, textures: &TextureMap, time: u32, speed: i32) {
+		let width  = textures.texture_width() as i32 * self.scale;
+		let height = textures.texture_height() as i32 * self.scale;
+
+		let amount = (self.bob * speed.clamp(0, self.full_speed) / self.full_speed.max(1)).to_fp();
+		let angle  = ((time % BOB_PERIOD) as i64 * trig::ANGLE_360 as i64 / BOB_PERIOD as i64) as i32;
+		let sway_x = fp::mul(amount, trig::cos(angle)).to_i32();
+		let sway_y = fp::mul(amount, trig::sin(angle)).to_i32().abs();
+
+		// the item is pushed down by the bob, so it never leaves a gap along the bottom edge
+		let x = (buf.width() - width) / 2 + sway_x;
+		let y = buf.height() - height + sway_y;
+
+		let src = Rect::new(0, 0, textures.texture_width() as i32, textures.texture_height() as i32);
+		Overlay::new(buf).blit_scaled(textures, self.frame(time), src, Rect::new(x, y, width, height));
+	}
+}
+
+impl TryFrom<&serde_json::Value> for Viewmodel {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		let texture = texture_id(&json["texture"]).ok_or("Viewmodel texture ids start at 1")?;
+		let firing  = if json["firing"].is_object() { Some(Animation::try_from(&json["firing"])?) } else { None };
+		let scale   = json["scale"].as_i64().map_or(4, |scale| scale as i32);
+		let mut viewmodel = Viewmodel::new(texture, firing, scale)?;
+
+		if let Some(bob) = json["bob"].as_i64() {
+			viewmodel.bob = bob as i32;
+		}
+
+		if let Some(full_speed) = json["full_speed"].as_i64() {
+			viewmodel.full_speed = full_speed as i32;
+		}
+
+		Ok(viewmodel)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_firing_plays_once() {
+		let mut viewmodel = Viewmodel::new(0, Some(Animation::new(vec![1, 2], 100).unwrap()), 1).unwrap();
+
+		assert!(viewmodel.fire(1000));
+		assert!(!viewmodel.fire(1050));
+		assert_eq!(1, viewmodel.frame(1050));
+		assert_eq!(2, viewmodel.frame(1150));
+		assert_eq!(0, viewmodel.frame(1200));
+		assert!(viewmodel.fire(1200));
+	}
+}
@@ -0,0 +1,116 @@
+use crate::render::RenderConfig;
+use crate::trig;
+use shared::consts;
+use shared::fp::ToFixedPoint;
+use shared::radian;
+
+// lookup tables which depend on the size of the display and the field of view,
+// generated at runtime rather than at compile time
+pub struct Viewport {
+	width: i32,
+	height: i32,
+	fov: i32,
+	distance: i32,
+	ray_angles: Vec<i32>,
+	fisheye: Vec<i32>,
+	wall_height: Vec<i32>,
+	wall_height_min: i32,
+	wall_height_max: i32,
+	wall_texture_step: Vec<i32>,
+}
+
+impl Viewport {
+	pub fn new(config: &RenderConfig, fov: i32) -> Viewport {
+		let width   = config.width;
+		let height  = config.height;
+
+		// distance from the camera to a projection plane which spans the field of view
+		let half_width = width as f64 / 2.0;
+		let distance   = half_width / radian!(fov >> 1).tan();
+
+		// cast the ray for each column through the centre of that column on the projection plane.
+		// Converting to the angle units of the trig tables means the angular resolution no
+		// longer depends on the number of columns
+		let ray_angles: Vec<i32> = (0..width)
+			.map(|column| (((column as f64 + 0.5 - half_width) / distance).atan() * trig::ANGLE_180 as f64 / std::f64::consts::PI).round() as i32)
+			.collect();
+		let fisheye = ray_angles.iter().map(|angle| (1.0 / radian!(*angle).cos()).to_fp()).collect();
+
+		let distance = distance as i32;
+
+		// height of a wall on screen is inversely proportional to its distance from the camera
+		let scale           = distance * consts::WALL_HEIGHT;
+		let wall_height_min = std::cmp::max(1, scale / consts::MAX_RAY_LENGTH);
+		let wall_height_max = scale / consts::WALL_DISTANCE_MIN;
+		let wall_height     = (0..=consts::MAX_RAY_LENGTH)
+			.map(|distance| scale / distance.max(consts::WALL_DISTANCE_MIN))
+			.collect();
+
+		// fixed point number of texture rows covered by each screen row of a wall
+		let wall_texture_step = (wall_height_min..=wall_height_max)
+			.map(|height| (consts::TEXTURE_HEIGHT as i32).to_fp() / height)
+			.collect();
+
+		Viewport { width, height, fov, distance, ray_angles, fisheye, wall_height, wall_height_min, wall_height_max, wall_texture_step }
+	}
+
+	pub fn width(&self) -> i32 {
+		self.width
+	}
+
+	pub fn height(&self) -> i32 {
+		self.height
+	}
+
+	pub fn fov(&self) -> i32 {
+		self.fov
+	}
+
+	// whether this is the display the compile time lookup tables were generated for
+	pub fn is_default(&self) -> bool {
+		self.width == consts::PROJECTION_PLANE_WIDTH && self.height == consts::PROJECTION_PLANE_HEIGHT && self.fov == trig::ANGLE_60
+	}
+
+	pub fn distance_to_projection_plane(&self) -> i32 {
+		self.distance
+	}
+
+	pub fn ray_angle(&self, column: i32) -> i32 {
+		self.ray_angles[column as usize]
+	}
+
+	pub fn fisheye_correction(&self, column: i32) -> i32 {
+		self.fisheye[column as usize]
+	}
+
+	pub fn wall_height(&self, distance: i32) -> i32 {
+		self.wall_height[distance.clamp(0, consts::MAX_RAY_LENGTH) as usize]
+	}
+
+	pub fn wall_texture_step(&self, height: i32) -> i32 {
+		self.wall_texture_step[(height.clamp(self.wall_height_min, self.wall_height_max) - self.wall_height_min) as usize]
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_rays_span_field_of_view() {
+		for fov in [trig::ANGLE_10, trig::ANGLE_60, trig::ANGLE_90] {
+			let viewport = Viewport::new(&RenderConfig::default(), fov);
+			let first    = viewport.ray_angle(0);
+			let last     = viewport.ray_angle(viewport.width() - 1);
+
+			assert_eq!(-first, last);
+			assert!((fov / 2 - last).abs() <= fov / viewport.width() + 1);
+		}
+	}
+
+	#[test]
+	fn test_default_projection_plane() {
+		let viewport = Viewport::new(&RenderConfig::default(), trig::ANGLE_60);
+		assert_eq!(277, viewport.distance_to_projection_plane());
+	}
+}
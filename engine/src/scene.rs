@@ -1,14 +1,265 @@
+use crate::render::{ Colour, Face };
 use serde_json;
+use shared::consts;
+use std::collections::{ HashMap, VecDeque };
+use shared::fp::{ ToFixedPoint, FromFixedPoint };
+
+// decals kept before the oldest are removed to make room for new ones
+const MAX_DECALS: usize = 64;
 
 pub struct TextureTile {
 	pub texture: u32,
+	pub animation: Option<u32>, // animation shown in place of the texture, if any
 	pub passable: bool,
+	pub offset: i32,    // walls only, how far the wall is set back from its grid line into the cell
+	pub height: i32,    // walls only, height in world units
+}
+
+impl TextureTile {
+	pub fn new(texture: u32) -> TextureTile {
+		TextureTile { texture, animation: None, passable: false, offset: 0, height: consts::WALL_HEIGHT }
+	}
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DoorState {
+	Closed,
+	Opening,
+	Open,
+	Closing,
+}
+
+// a door sits half a tile into the cell to the right of (x walls) or below (y walls)
+// the edge it is placed on, and slides sideways as it opens
+pub struct DoorTile {
+	pub texture: u32,
+	pub open: i32,          // how far the door has slid open in fixed point world units
+	pub state: DoorState,
+}
+
+impl DoorTile {
+	pub fn new(texture: u32) -> DoorTile {
+		DoorTile { texture, open: 0, state: DoorState::Closed }
+	}
+
+	// number of texture columns the door has slid out of view
+	pub fn open_amount(&self) -> i32 {
+		self.open.to_i32()
+	}
+
+	pub fn is_passable(&self) -> bool {
+		self.state == DoorState::Open
+	}
+
+	pub fn open(&mut self) {
+		if self.state == DoorState::Closed || self.state == DoorState::Closing {
+			self.state = DoorState::Opening;
+		}
+	}
+
+	pub fn close(&mut self) {
+		if self.state == DoorState::Open || self.state == DoorState::Opening {
+			self.state = DoorState::Closing;
+		}
+	}
+
+	pub fn toggle(&mut self) {
+		match self.state {
+			DoorState::Closed | DoorState::Closing => self.open(),
+			DoorState::Open   | DoorState::Opening => self.close(),
+		}
+	}
+
+	// slide the door by the time elapsed in milliseconds
+	pub fn update(&mut self, elapsed: u32) {
+		let distance = (consts::FP_TILE_SIZE as i64 * elapsed as i64 / consts::DOOR_OPEN_TIME as i64) as i32;
+
+		match self.state {
+			DoorState::Opening => {
+				self.open = std::cmp::min(consts::FP_TILE_SIZE, self.open.saturating_add(distance));
+				if self.open == consts::FP_TILE_SIZE { self.state = DoorState::Open; }
+			},
+			DoorState::Closing => {
+				self.open = std::cmp::max(0, self.open.saturating_sub(distance));
+				if self.open == 0 { self.state = DoorState::Closed; }
+			},
+			_ => {},
+		}
+	}
+}
+
+// a block of four walls sliding from one cell to another, positioned by its top left
+// corner in fixed point world units
+pub struct PushWall {
+	pub x: i32,
+	pub y: i32,
+	pub dir_x: i32,
+	pub dir_y: i32,
+	pub remaining: i32,     // fixed point distance left to slide
+	pub textures: [u32; 4], // textures of the left, right, top and bottom faces
+	pub animations: [Option<u32>; 4],
+	pub own: [bool; 4],     // which faces are the block's, rather than walls of a corridor it slides along
+}
+
+impl PushWall {
+	// world space bounds of the block as left, top, right and bottom
+	pub fn bounds(&self) -> (i32, i32, i32, i32) {
+		let x = self.x.to_i32();
+		let y = self.y.to_i32();
+		(x, y, x + consts::TILE_SIZE, y + consts::TILE_SIZE)
+	}
+}
+
+pub struct Sprite {
+	pub x: i32,
+	pub y: i32,
+	pub texture: u32,
+	pub animation: Option<u32>,
+}
+
+impl Sprite {
+	pub fn new(x: i32, y: i32, texture: u32) -> Sprite {
+		Sprite { x, y, texture, animation: None }
+	}
+}
+
+// a texture stuck over part of a wall face, such as a bullet hole or a sign. It is centred x
+// units along the face, measured the same way as the wall's texture column, and y units above
+// the floor, and covers a square size units across
+#[derive(Copy, Clone)]
+pub struct Decal {
+	pub texture: u32,
+	pub x: i32,
+	pub y: i32,
+	pub size: i32,
+}
+
+impl Decal {
+	pub fn new(texture: u32, x: i32, y: i32, size: i32) -> Decal {
+		Decal { texture, x, y, size }
+	}
+}
+
+// light given off by something in the scene, such as a lamp or a muzzle flash, which brightens
+// surfaces within radius world units of it by up to intensity / 255 of its colour, fading out
+// towards the edge. Lights which cast shadows don't reach surfaces with a wall in between
+#[derive(Copy, Clone)]
+pub struct PointLight {
+	pub x: i32,
+	pub y: i32,
+	pub radius: i32,
+	pub colour: Colour,
+	pub intensity: u8,
+	pub shadows: bool,
+}
+
+impl PointLight {
+	pub fn new(x: i32, y: i32, radius: i32, colour: Colour, intensity: u8) -> PointLight {
+		PointLight { x, y, radius, colour, intensity, shadows: false }
+	}
+
+	// amount of the light, from 0 to 255, reaching a point
+	fn amount_at(&self, x: i32, y: i32) -> u32 {
+		let (dx, dy) = ((x - self.x) as i64, (y - self.y) as i64);
+		let radius   = self.radius as i64;
+		let distance = dx * dx + dy * dy;
+		if distance >= radius * radius { return 0; }
+
+		let distance = (distance as f64).sqrt() as i64;
+		(self.intensity as i64 * (radius - distance) / radius) as u32
+	}
+}
+
+// which cells each point light casting shadows reaches, worked out once a frame so that floors
+// and ceilings don't trace a line back to every light for every pixel
+#[derive(Default)]
+pub struct LitCells {
+	width: i32,
+	lights: Vec<Option<Vec<bool>>>, // by point light, none for those without shadows
+}
+
+impl LitCells {
+	// none if the light casts no shadows or was added since the cells were worked out
+	fn reaches(&self, light: usize, (x, y): (i32, i32)) -> Option<bool> {
+		let cells = self.lights.get(light)?.as_ref()?;
+		Some(x >= 0 && x < self.width && cells.get((x + y * self.width) as usize).copied().unwrap_or(false))
+	}
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum LightEffect {
+	Steady,
+	Flicker,
+	Pulse,
+	Strobe,
+}
+
+impl TryFrom<&serde_json::Value> for LightEffect {
+	type Error = &'static str;
+
+	fn try_from(json: &serde_json::Value) -> Result<Self, Self::Error> {
+		match json.as_str() {
+			None | Some("steady") => Ok(LightEffect::Steady),
+			Some("flicker")       => Ok(LightEffect::Flicker),
+			Some("pulse")         => Ok(LightEffect::Pulse),
+			Some("strobe")        => Ok(LightEffect::Strobe),
+			Some(_)               => Err("Light effect must be one of steady, flicker, pulse or strobe"),
+		}
+	}
+}
+
+// brightness of a tile, from 0 (black) to 255 (full brightness). Effects vary the
+// brightness between min and level over a period given in milliseconds
+#[derive(Copy, Clone)]
+pub struct Light {
+	pub level: u8,
+	pub min: u8,
+	pub effect: LightEffect,
+	pub period: u32,
+}
+
+impl Light {
+	pub fn new(level: u8) -> Light {
+		Light { level, min: level, effect: LightEffect::Steady, period: 1000 }
+	}
+
+	pub fn with_effect(level: u8, min: u8, effect: LightEffect, period: u32) -> Result<Light, &'static str> {
+		if period == 0 {
+			return Err("Light effect period must be greater than zero");
+		}
+
+		Ok(Light { level, min, effect, period })
+	}
+
+	// brightness at the given time. The tile position offsets flickering so that
+	// neighbouring tiles don't flicker in step
+	pub fn level_at(&self, x: i32, y: i32, time: u32) -> u8 {
+		let phase = time % self.period;
+
+		match self.effect {
+			LightEffect::Steady  => self.level,
+			LightEffect::Strobe  => if phase < self.period / 2 { self.level } else { self.min },
+			LightEffect::Pulse   => {
+				// triangle wave, dimming to min half way through the period
+				let t = 2 * phase.abs_diff(self.period / 2) as i64;
+				(self.min as i64 + (self.level as i64 - self.min as i64) * t / self.period as i64).clamp(0, 255) as u8
+			},
+			LightEffect::Flicker => {
+				let mut hash = (x as u32).wrapping_mul(73856093) ^ (y as u32).wrapping_mul(19349663) ^ (time / self.period).wrapping_mul(83492791);
+				hash ^= hash >> 13;
+				hash  = hash.wrapping_mul(0x5bd1e995);
+				hash ^= hash >> 15;
+				if hash & 3 == 0 { self.min } else { self.level }
+			},
+		}
+	}
 }
 
 pub enum Tile {
 	OutOfBounds,
 	Empty,
 	Surface(TextureTile),
+	Door(DoorTile),
 }
 
 pub struct Scene {
@@ -18,6 +269,15 @@ pub struct Scene {
 	x_walls: Vec<Tile>,
 	floor: Vec<Tile>,
 	ceiling: Vec<Tile>,
+	sprites: Vec<Sprite>,
+	lights: Vec<Light>,
+	pushable: Vec<(i32, i32, i32)>,
+	push_walls: Vec<PushWall>,
+	sky: Vec<u32>,
+	decals: HashMap<(i32, i32, Face), Vec<Decal>>,
+	decal_order: VecDeque<(i32, i32, Face)>,
+	max_decals: usize,
+	point_lights: Vec<PointLight>,
 }
 
 impl Scene {
@@ -26,7 +286,18 @@ impl Scene {
 			return Err("Width and height must be positive values");
 		}
 
-		Ok(Scene { width, height, y_walls, x_walls, floor, ceiling })
+		// every tile is fully lit unless told otherwise
+		let lights = vec![Light::new(255); (width * height) as usize];
+
+		Ok(Scene { width, height, y_walls, x_walls, floor, ceiling, sprites: Vec::new(), lights, pushable: Vec::new(), push_walls: Vec::new(), sky: Vec::new(), decals: HashMap::new(), decal_order: VecDeque::new(), max_decals: MAX_DECALS, point_lights: Vec::new() })
+	}
+
+	pub fn width(&self) -> i32 {
+		self.width
+	}
+
+	pub fn height(&self) -> i32 {
+		self.height
 	}
 
 	pub fn is_within_bounds(&self, x: i32, y: i32) -> bool {
@@ -52,6 +323,358 @@ impl Scene {
 		if !self.is_within_bounds(x, y) { return &Tile::OutOfBounds; }
 		&self.floor[(x + y  * self.width) as usize]
 	}
+
+	pub fn set_y_wall(&mut self, x: i32, y: i32, tile: Tile) {
+		if !self.is_within_bounds(x, y) { return; }
+		self.y_walls[(x + y * self.width) as usize] = tile;
+	}
+
+	pub fn set_x_wall(&mut self, x: i32, y: i32, tile: Tile) {
+		if !self.is_within_bounds(x, y) { return; }
+		self.x_walls[(x + y * self.width) as usize] = tile;
+	}
+
+	// door on either wall at a grid coordinate, x walls first
+	pub fn door_mut(&mut self, x: i32, y: i32) -> Option<&mut DoorTile> {
+		if !self.is_within_bounds(x, y) { return None; }
+		let idx = (x + y * self.width) as usize;
+
+		if let Tile::Door(door) = &mut self.x_walls[idx] { return Some(door); }
+		if let Tile::Door(door) = &mut self.y_walls[idx] { return Some(door); }
+		None
+	}
+
+	pub fn set_door(&mut self, x: i32, y: i32, on_x_wall: bool, door: DoorTile) {
+		if !self.is_within_bounds(x, y) { return; }
+		let idx = (x + y * self.width) as usize;
+
+		if on_x_wall {
+			self.x_walls[idx] = Tile::Door(door);
+		} else {
+			self.y_walls[idx] = Tile::Door(door);
+		}
+	}
+
+	// returns false if there is no door at the coordinate
+	pub fn open_door(&mut self, x: i32, y: i32) -> bool {
+		self.door_mut(x, y).map(|door| door.open()).is_some()
+	}
+
+	pub fn close_door(&mut self, x: i32, y: i32) -> bool {
+		self.door_mut(x, y).map(|door| door.close()).is_some()
+	}
+
+	pub fn toggle_door(&mut self, x: i32, y: i32) -> bool {
+		self.door_mut(x, y).map(|door| door.toggle()).is_some()
+	}
+
+	// mark the block of walls around a cell as one which slides up to distance tiles when pushed
+	pub fn add_push_wall(&mut self, x: i32, y: i32, distance: i32) {
+		self.pushable.push((x, y, distance));
+	}
+
+	// push walls which are currently sliding
+	pub fn push_walls(&self) -> &[PushWall] {
+		&self.push_walls
+	}
+
+	// start the push wall at a grid coordinate sliding one unit step along one axis. Returns
+	// false if there is no push wall there, the direction is not a unit step or it is blocked
+	pub fn push_wall(&mut self, x: i32, y: i32, dir_x: i32, dir_y: i32) -> bool {
+		if dir_x.abs() + dir_y.abs() != 1 { return false; }
+		let Some(idx) = self.pushable.iter().position(|&(px, py, _)| px == x && py == y) else { return false; };
+		let distance = self.pushable[idx].2;
+
+		let mut textures   = [0; 4];
+		let mut animations = [None; 4];
+		for side in 0..4 {
+			match self.cell_wall(x, y, side) {
+				Tile::Surface(wall) => (textures[side], animations[side]) = (wall.texture, wall.animation),
+				_                   => return false,
+			}
+		}
+
+		// slide as far as possible, stopping short of any wall in the way
+		let mut tiles = 0;
+		while tiles < distance {
+			let (next_x, next_y) = (x + (tiles + 1) * dir_x, y + (tiles + 1) * dir_y);
+			if !self.is_within_bounds(next_x, next_y) { break; }
+			if tiles > 0 && !matches!(self.edge_between(x + tiles * dir_x, y + tiles * dir_y, dir_x, dir_y), Tile::Empty) { break; }
+			tiles += 1;
+		}
+
+		if tiles == 0 { return false; }
+
+		// the faces across the way the block slides go with it. A face alongside it is the wall of
+		// a corridor rather than the block's own when the wall on the same grid line carries on
+		// into the next cell, and is left where it is
+		let mut own = [true; 4];
+		for side in if dir_x != 0 { [2, 3] } else { [0, 1] } {
+			own[side] = !matches!(self.cell_wall(x + dir_x, y + dir_y, side), Tile::Surface(_));
+		}
+
+		self.pushable.remove(idx);
+		for side in (0..4).filter(|&side| own[side]) {
+			self.set_cell_wall(x, y, side, Tile::Empty);
+		}
+
+		self.push_walls.push(PushWall {
+			x: (x * consts::TILE_SIZE).to_fp(),
+			y: (y * consts::TILE_SIZE).to_fp(),
+			dir_x,
+			dir_y,
+			remaining: tiles * consts::FP_TILE_SIZE,
+			textures,
+			animations,
+			own,
+		});
+
+		true
+	}
+
+	// one of the four walls around a cell, in the order of the faces of a push wall: left, right,
+	// top and bottom
+	fn cell_wall(&self, x: i32, y: i32, side: usize) -> &Tile {
+		match side {
+			0 => self.x_wall(x, y),
+			1 => self.x_wall(x + 1, y),
+			2 => self.y_wall(x, y),
+			_ => self.y_wall(x, y + 1),
+		}
+	}
+
+	fn set_cell_wall(&mut self, x: i32, y: i32, side: usize, tile: Tile) {
+		match side {
+			0 => self.set_x_wall(x, y, tile),
+			1 => self.set_x_wall(x + 1, y, tile),
+			2 => self.set_y_wall(x, y, tile),
+			_ => self.set_y_wall(x, y + 1, tile),
+		}
+	}
+
+	// the wall separating a cell from its neighbour in a direction along one axis
+	fn edge_between(&self, x: i32, y: i32, dir_x: i32, dir_y: i32) -> &Tile {
+		match (dir_x, dir_y) {
+			(1, _)  => self.x_wall(x + 1, y),
+			(-1, _) => self.x_wall(x, y),
+			(_, 1)  => self.y_wall(x, y + 1),
+			_       => self.y_wall(x, y),
+		}
+	}
+
+	// advance anything animated in the scene by the time elapsed in milliseconds
+	pub fn update(&mut self, elapsed: u32) {
+		for tile in self.x_walls.iter_mut().chain(self.y_walls.iter_mut()) {
+			if let Tile::Door(door) = tile {
+				door.update(elapsed);
+			}
+		}
+
+		let distance = (consts::FP_TILE_SIZE as i64 * elapsed as i64 / consts::PUSH_WALL_MOVE_TIME as i64) as i32;
+
+		for block in self.push_walls.iter_mut() {
+			let step = std::cmp::min(distance, block.remaining);
+			block.x += step * block.dir_x;
+			block.y += step * block.dir_y;
+			block.remaining -= step;
+		}
+
+		// blocks which have finished sliding become walls again in their new cell
+		let (arrived, moving): (Vec<PushWall>, Vec<PushWall>) = std::mem::take(&mut self.push_walls).into_iter().partition(|block| block.remaining == 0);
+		self.push_walls = moving;
+
+		for block in arrived {
+			let (left, top, _, _) = block.bounds();
+			let (x, y) = (left / consts::TILE_SIZE, top / consts::TILE_SIZE);
+			for side in (0..4).filter(|&side| block.own[side]) {
+				self.set_cell_wall(x, y, side, Tile::Surface(TextureTile { animation: block.animations[side], ..TextureTile::new(block.textures[side]) }));
+			}
+		}
+	}
+
+	pub fn light(&self, x: i32, y: i32) -> Option<&Light> {
+		if !self.is_within_bounds(x, y) { return None; }
+		Some(&self.lights[(x + y * self.width) as usize])
+	}
+
+	pub fn set_light(&mut self, x: i32, y: i32, light: Light) {
+		if !self.is_within_bounds(x, y) { return; }
+		self.lights[(x + y * self.width) as usize] = light;
+	}
+
+	// brightness of a tile at the given time in milliseconds
+	pub fn light_level(&self, x: i32, y: i32, time: u32) -> u8 {
+		self.light(x, y).map_or(255, |light| light.level_at(x, y, time))
+	}
+
+	// point lights are expected to come and go, so are usually cleared and added again each frame
+	pub fn point_lights(&self) -> &[PointLight] {
+		&self.point_lights
+	}
+
+	pub fn add_point_light(&mut self, light: PointLight) -> usize {
+		self.point_lights.push(light);
+		self.point_lights.len() - 1
+	}
+
+	pub fn clear_point_lights(&mut self) {
+		self.point_lights.clear();
+	}
+
+	// colour of the light from point lights reaching world x, y, added together
+	pub fn glow_at(&self, x: i32, y: i32) -> Colour {
+		self.glow(x, y, |_, light| self.is_visible((light.x, light.y), (x, y)))
+	}
+
+	// as glow_at, for a point on the floor or ceiling, where whether each light reaches it is
+	// taken from the cell it lies in rather than traced back to the light
+	pub fn surface_glow_at(&self, lit: &LitCells, x: i32, y: i32) -> Colour {
+		let cell = (x >> consts::TILE_SHIFT, y >> consts::TILE_SHIFT);
+		self.glow(x, y, |idx, light| lit.reaches(idx, cell).unwrap_or_else(|| self.is_visible((light.x, light.y), (x, y))))
+	}
+
+	fn glow(&self, x: i32, y: i32, visible: impl Fn(usize, &PointLight) -> bool) -> Colour {
+		let (mut r, mut g, mut b) = (0, 0, 0);
+		if self.point_lights.is_empty() { return Colour::new(0, 0, 0, 0); }
+
+		for (idx, light) in self.point_lights.iter().enumerate() {
+			let amount = light.amount_at(x, y);
+			if amount == 0 || (light.shadows && !visible(idx, light)) { continue; }
+
+			r += light.colour.r as u32 * amount / 255;
+			g += light.colour.g as u32 * amount / 255;
+			b += light.colour.b as u32 * amount / 255;
+		}
+
+		Colour::new(r.min(255) as u8, g.min(255) as u8, b.min(255) as u8, 0xFF)
+	}
+
+	// which cells the point lights casting shadows can see the middle of
+	pub fn lit_cells(&self) -> LitCells {
+		let lights = self.point_lights.iter().map(|light| {
+			if !light.shadows { return None; }
+
+			let (light_x, light_y) = (light.x >> consts::TILE_SHIFT, light.y >> consts::TILE_SHIFT);
+			let reach = (light.radius >> consts::TILE_SHIFT) + 1;
+			let mut cells = vec![false; (self.width * self.height) as usize];
+
+			for y in (light_y - reach).max(0)..=(light_y + reach).min(self.height - 1) {
+				for x in (light_x - reach).max(0)..=(light_x + reach).min(self.width - 1) {
+					let centre = (x * consts::TILE_SIZE + (consts::TILE_SIZE >> 1), y * consts::TILE_SIZE + (consts::TILE_SIZE >> 1));
+					cells[(x + y * self.width) as usize] = (x, y) == (light_x, light_y) || self.is_visible((light.x, light.y), centre);
+				}
+			}
+
+			Some(cells)
+		}).collect();
+
+		LitCells { width: self.width, lights }
+	}
+
+	// whether a straight line between two world points crosses no walls or closed doors. Walls
+	// at either end don't count, so a point on a wall can be seen from in front of it
+	pub fn is_visible(&self, from: (i32, i32), to: (i32, i32)) -> bool {
+		!self.crosses_wall(from, to, false) && !self.crosses_wall((from.1, from.0), (to.1, to.0), true)
+	}
+
+	// whether a line crosses a wall on or set back from a line across the x axis. Walls across the
+	// y axis are found the same way, with the coordinates swapped
+	fn crosses_wall(&self, from: (i32, i32), to: (i32, i32), swapped: bool) -> bool {
+		let (x0, y0, x1, y1) = (from.0 as i64, from.1 as i64, to.0 as i64, to.1 as i64);
+		if x0 == x1 { return false; }
+
+		let tile = consts::TILE_SIZE as i64;
+		let at   = |x: i64| y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+		let (x_min, x_max) = (x0.min(x1), x0.max(x1));
+
+		// every band of cells the line passes through may hold a wall set back into it
+		for grid_x in x_min.div_euclid(tile)..=x_max.div_euclid(tile) {
+			let row_0 = at((grid_x * tile).max(x_min)).div_euclid(tile);
+			let row_1 = at((grid_x * tile + tile - 1).min(x_max)).div_euclid(tile);
+
+			for grid_y in row_0.min(row_1)..=row_0.max(row_1) {
+				let wall = if swapped { self.y_wall(grid_y as i32, grid_x as i32) } else { self.x_wall(grid_x as i32, grid_y as i32) };
+				let offset = match wall {
+					Tile::Surface(wall)                     => wall.offset,
+					Tile::Door(door) if !door.is_passable() => consts::TILE_SIZE >> 1,
+					_                                       => continue,
+				};
+
+				let x = grid_x * tile + offset as i64;
+				if x > x_min && x < x_max && at(x).div_euclid(tile) == grid_y { return true; }
+			}
+		}
+
+		false
+	}
+
+	pub fn sprites(&self) -> &[Sprite] {
+		&self.sprites
+	}
+
+	pub fn sprites_mut(&mut self) -> &mut [Sprite] {
+		&mut self.sprites
+	}
+
+	pub fn add_sprite(&mut self, sprite: Sprite) -> usize {
+		self.sprites.push(sprite);
+		self.sprites.len() - 1
+	}
+
+	// textures laid side by side to form a panorama wrapping all the way around the
+	// camera, seen wherever there is no ceiling
+	pub fn sky(&self) -> &[u32] {
+		&self.sky
+	}
+
+	pub fn set_sky(&mut self, textures: Vec<u32>) {
+		self.sky = textures;
+	}
+
+	// decals on a face of the wall at tile x, y, oldest first
+	pub fn decals(&self, x: i32, y: i32, face: Face) -> &[Decal] {
+		self.decals.get(&(x, y, face)).map_or(&[], |decals| decals)
+	}
+
+	// sticks a decal on a face of the wall at tile x, y, as reported when the wall is picked or
+	// hit by a ray. Once there are too many decals the oldest are removed to make room
+	pub fn add_decal(&mut self, x: i32, y: i32, face: Face, decal: Decal) -> bool {
+		let wall = match face {
+			Face::North | Face::South => self.y_wall(x, y),
+			Face::East | Face::West   => self.x_wall(x, y),
+			Face::Up | Face::Down     => return false,
+		};
+
+		if !matches!(wall, Tile::Surface(_) | Tile::Door(_)) || decal.size <= 0 || self.max_decals == 0 {
+			return false;
+		}
+
+		self.decals.entry((x, y, face)).or_default().push(decal);
+		self.decal_order.push_back((x, y, face));
+		self.evict_decals();
+		true
+	}
+
+	pub fn set_max_decals(&mut self, max_decals: usize) {
+		self.max_decals = max_decals;
+		self.evict_decals();
+	}
+
+	pub fn clear_decals(&mut self) {
+		self.decals.clear();
+		self.decal_order.clear();
+	}
+
+	// decals on each face are kept in the order they were added, so the oldest overall is the
+	// first on the face added to longest ago
+	fn evict_decals(&mut self) {
+		while self.decal_order.len() > self.max_decals {
+			let key = self.decal_order.pop_front().unwrap();
+			let decals = self.decals.get_mut(&key).unwrap();
+			decals.remove(0);
+			if decals.is_empty() { self.decals.remove(&key); }
+		}
+	}
 }
 
 impl TryFrom<&serde_json::Value> for Scene {
@@ -61,27 +684,231 @@ impl TryFrom<&serde_json::Value> for Scene {
 		let width  = json["width"].as_i64().unwrap() as i32;
 		let height = json["height"].as_i64().unwrap() as i32;
 		
-		let x_walls = json["x_walls"].as_array().unwrap().iter()
+		let mut x_walls: Vec<Tile> = json["x_walls"].as_array().unwrap().iter()
 			.map(|value|   { value.as_i64().unwrap() as u32 })
-			.map(|texture| { if texture > 0 { Tile::Surface(TextureTile { texture: texture - 1, passable: false }) } else { Tile::Empty } })
+			.map(|texture| { if texture > 0 { Tile::Surface(TextureTile::new(texture - 1)) } else { Tile::Empty } })
 			.collect();
 
-		let y_walls = json["y_walls"].as_array().unwrap().iter()
+		let mut y_walls: Vec<Tile> = json["y_walls"].as_array().unwrap().iter()
 			.map(|value|   { value.as_i64().unwrap() as u32 })
-			.map(|texture| { if texture > 0 { Tile::Surface(TextureTile { texture: texture - 1, passable: false }) } else { Tile::Empty } })
+			.map(|texture| { if texture > 0 { Tile::Surface(TextureTile::new(texture - 1)) } else { Tile::Empty } })
 			.collect();
 
-		let floor = json["floor"].as_array().unwrap().iter()
+		let mut floor: Vec<Tile> = json["floor"].as_array().unwrap().iter()
 			.map(|value|   { value.as_i64().unwrap() as u32 })
-			.map(|texture| { if texture > 0 { Tile::Surface(TextureTile { texture: texture - 1, passable: false }) } else { Tile::Empty } })
+			.map(|texture| { if texture > 0 { Tile::Surface(TextureTile::new(texture - 1)) } else { Tile::Empty } })
 			.collect();
 
-		let ceiling = json["ceiling"].as_array().unwrap().iter()
+		let mut ceiling: Vec<Tile> = json["ceiling"].as_array().unwrap().iter()
 			.map(|value|   { value.as_i64().unwrap() as u32 })
-			.map(|texture| { if texture > 0 { Tile::Surface(TextureTile { texture: texture - 1, passable: false }) } else { Tile::Empty } })
+			.map(|texture| { if texture > 0 { Tile::Surface(TextureTile::new(texture - 1)) } else { Tile::Empty } })
 			.collect();
 
-		Scene::new(width, height, x_walls, y_walls, floor, ceiling)
+		// walls may optionally be set back from their grid line into the cell
+		set_wall_offsets(&mut x_walls, &json["x_wall_offsets"])?;
+		set_wall_offsets(&mut y_walls, &json["y_wall_offsets"])?;
+
+		// and be taller or shorter than usual, given as a multiple of the usual height
+		set_wall_heights(&mut x_walls, &json["x_wall_heights"])?;
+		set_wall_heights(&mut y_walls, &json["y_wall_heights"])?;
+
+		// any tile may show one of the renderer's animations instead of its texture
+		set_animations(&mut x_walls, &json["x_wall_animations"])?;
+		set_animations(&mut y_walls, &json["y_wall_animations"])?;
+		set_animations(&mut floor, &json["floor_animations"])?;
+		set_animations(&mut ceiling, &json["ceiling_animations"])?;
+
+		let mut scene = Scene::new(width, height, y_walls, x_walls, floor, ceiling)?;
+
+		// sprites are optional
+		if let Some(sprites) = json["sprites"].as_array() {
+			for sprite in sprites {
+				let x       = sprite["x"].as_i64().unwrap() as i32;
+				let y       = sprite["y"].as_i64().unwrap() as i32;
+				let texture = texture_id(&sprite["texture"]).ok_or("Sprite texture ids start at 1")?;
+
+				let animation = match sprite["animation"].as_u64() {
+					Some(0)         => return Err("Animation ids start at 1"),
+					Some(animation) => Some(animation as u32 - 1),
+					None            => None,
+				};
+				scene.add_sprite(Sprite { animation, ..Sprite::new(x, y, texture) });
+			}
+		}
+
+		// doors replace whatever is on the wall they are placed on
+		if let Some(doors) = json["doors"].as_array() {
+			for door in doors {
+				let x       = door["x"].as_i64().unwrap() as i32;
+				let y       = door["y"].as_i64().unwrap() as i32;
+				let texture = texture_id(&door["texture"]).ok_or("Door texture ids start at 1")?;
+				if !scene.is_within_bounds(x, y) { return Err("Door must be placed within the scene"); }
+
+				match door["wall"].as_str() {
+					Some("x") => scene.set_door(x, y, true, DoorTile::new(texture)),
+					Some("y") => scene.set_door(x, y, false, DoorTile::new(texture)),
+					_         => return Err("Door wall must be either x or y"),
+				}
+			}
+		}
+
+		// push walls are cells enclosed by walls which slide when pushed
+		if let Some(push_walls) = json["push_walls"].as_array() {
+			for push_wall in push_walls {
+				let x        = push_wall["x"].as_i64().unwrap() as i32;
+				let y        = push_wall["y"].as_i64().unwrap() as i32;
+				let distance = push_wall["distance"].as_i64().unwrap_or(1) as i32;
+				if !scene.is_within_bounds(x, y) { return Err("Push wall must be placed within the scene"); }
+				if distance < 1 { return Err("Push wall must slide at least one tile"); }
+				scene.add_push_wall(x, y, distance);
+			}
+		}
+
+		// light levels are optional and given per tile like the floor and ceiling
+		if let Some(lights) = json["lights"].as_array() {
+			if lights.len() != (width * height) as usize { return Err("Light levels must cover every tile"); }
+			for (idx, level) in lights.iter().enumerate() {
+				let level = level.as_u64().ok_or("Light levels must be positive integers")?.min(255) as u8;
+				scene.set_light(idx as i32 % width, idx as i32 / width, Light::new(level));
+			}
+		}
+
+		// light effects are applied to individual tiles on top of their light level
+		if let Some(effects) = json["light_effects"].as_array() {
+			for effect in effects {
+				let x      = effect["x"].as_i64().unwrap() as i32;
+				let y      = effect["y"].as_i64().unwrap() as i32;
+				let level  = scene.light(x, y).ok_or("Light effect must be placed within the scene")?.level;
+				let min    = effect["min"].as_u64().map_or(0, |min| min.min(255) as u8);
+				let period = effect["period"].as_u64().unwrap_or(1000) as u32;
+				scene.set_light(x, y, Light::with_effect(level, min, LightEffect::try_from(&effect["effect"])?, period)?);
+			}
+		}
+
+		// the sky is optional, outdoor scenes list the textures making up its panorama
+		if let Some(sky) = json["sky"].as_array() {
+			let textures = sky.iter()
+				.map(texture_id)
+				.collect::<Option<Vec<u32>>>()
+				.ok_or("Sky texture ids start at 1")?;
+			scene.set_sky(textures);
+		}
+
+		Ok(scene)
 	}
 }
 
+// texture ids in level files count from 1, leaving 0 free to mean no texture at all, as it does
+// for the tiles of a scene. Everything else referring to a texture numbers them the same way
+pub fn texture_id(json: &serde_json::Value) -> Option<u32> {
+	json.as_u64().filter(|texture| *texture > 0).map(|texture| texture as u32 - 1)
+}
+
+fn set_wall_offsets(walls: &mut [Tile], json: &serde_json::Value) -> Result<(), &'static str> {
+	let Some(offsets) = json.as_array() else { return Ok(()); };
+	if offsets.len() != walls.len() { return Err("Wall offsets must cover every tile"); }
+
+	for (wall, offset) in walls.iter_mut().zip(offsets) {
+		let offset = offset.as_i64().ok_or("Wall offsets must be integers")? as i32;
+		if !(0..consts::TILE_SIZE).contains(&offset) { return Err("Wall offsets must lie within a tile"); }
+		if let Tile::Surface(wall) = wall { wall.offset = offset; }
+	}
+
+	Ok(())
+}
+
+fn set_wall_heights(walls: &mut [Tile], json: &serde_json::Value) -> Result<(), &'static str> {
+	let Some(heights) = json.as_array() else { return Ok(()); };
+	if heights.len() != walls.len() { return Err("Wall heights must cover every tile"); }
+
+	for (wall, height) in walls.iter_mut().zip(heights) {
+		let height = (height.as_f64().ok_or("Wall heights must be numbers")? * consts::WALL_HEIGHT as f64).round() as i32;
+		if height <= 0 { return Err("Wall heights must be greater than zero"); }
+		if let Tile::Surface(wall) = wall { wall.height = height; }
+	}
+
+	Ok(())
+}
+
+// animation ids follow the order of the renderer's animations, starting at 1, with 0 for none
+fn set_animations(tiles: &mut [Tile], json: &serde_json::Value) -> Result<(), &'static str> {
+	let Some(animations) = json.as_array() else { return Ok(()); };
+	if animations.len() != tiles.len() { return Err("Animations must cover every tile"); }
+
+	for (tile, animation) in tiles.iter_mut().zip(animations) {
+		let animation = animation.as_u64().ok_or("Animation ids must be positive integers")? as u32;
+		if animation == 0 { continue; }
+		if let Tile::Surface(tile) = tile { tile.animation = Some(animation - 1); }
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	// an open scene with no walls, with the given extra fields
+	fn scene_json(width: i32, height: i32, extra: serde_json::Value) -> serde_json::Value {
+		let tiles    = vec![0; (width * height) as usize];
+		let mut json = serde_json::json!({ "width": width, "height": height, "x_walls": tiles, "y_walls": tiles, "floor": tiles, "ceiling": tiles });
+		json.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+		json
+	}
+
+	#[test]
+	fn test_light_effects() {
+		let strobe = Light::with_effect(200, 50, LightEffect::Strobe, 1000).unwrap();
+		assert_eq!(200, strobe.level_at(0, 0, 0));
+		assert_eq!(200, strobe.level_at(0, 0, 499));
+		assert_eq!(50, strobe.level_at(0, 0, 500));
+		assert_eq!(200, strobe.level_at(0, 0, 1000));
+
+		// dims to min half way through the period and back again
+		let pulse = Light::with_effect(200, 0, LightEffect::Pulse, 1000).unwrap();
+		assert_eq!(200, pulse.level_at(0, 0, 0));
+		assert_eq!(100, pulse.level_at(0, 0, 250));
+		assert_eq!(0, pulse.level_at(0, 0, 500));
+		assert_eq!(100, pulse.level_at(0, 0, 750));
+		assert_eq!(200, pulse.level_at(0, 0, 1000));
+
+		// flickering holds either level for a whole period, and not in step across tiles
+		let flicker = Light::with_effect(200, 10, LightEffect::Flicker, 100).unwrap();
+		let levels  = (0..64).map(|period| flicker.level_at(3, 4, period * 100)).collect::<Vec<u8>>();
+		assert!(levels.iter().all(|&level| level == 200 || level == 10));
+		assert!(levels.contains(&200) && levels.contains(&10));
+		assert!((0..64).all(|period| flicker.level_at(3, 4, period * 100) == flicker.level_at(3, 4, period * 100 + 99)));
+		assert!((0..64).any(|period| flicker.level_at(3, 4, period * 100) != flicker.level_at(4, 3, period * 100)));
+
+		assert_eq!(Err("Light effect period must be greater than zero"), Light::with_effect(200, 10, LightEffect::Strobe, 0).map(|_| ()));
+	}
+
+	#[test]
+	fn test_light_levels_from_json() {
+		let json  = scene_json(2, 2, serde_json::json!({
+			"lights": [ 255, 128, 64, 300 ],
+			"light_effects": [ { "x": 1, "y": 0, "effect": "strobe", "min": 16, "period": 200 } ],
+		}));
+		let scene = Scene::try_from(&json).unwrap();
+
+		assert_eq!(255, scene.light_level(0, 0, 0));
+		assert_eq!(64, scene.light_level(0, 1, 0));
+		assert_eq!(255, scene.light_level(1, 1, 0));
+
+		// the effect keeps the tile's level as its brightest
+		assert_eq!(128, scene.light_level(1, 0, 0));
+		assert_eq!(16, scene.light_level(1, 0, 100));
+
+		// outside the scene is fully lit
+		assert_eq!(255, scene.light_level(2, 0, 0));
+
+		let json = scene_json(2, 2, serde_json::json!({ "lights": [ 255, 128, 64 ] }));
+		assert_eq!(Some("Light levels must cover every tile"), Scene::try_from(&json).err());
+
+		let json = scene_json(2, 2, serde_json::json!({ "light_effects": [ { "x": 2, "y": 0, "effect": "pulse" } ] }));
+		assert_eq!(Some("Light effect must be placed within the scene"), Scene::try_from(&json).err());
+
+		let json = scene_json(2, 2, serde_json::json!({ "light_effects": [ { "x": 0, "y": 0, "effect": "sparkle" } ] }));
+		assert_eq!(Some("Light effect must be one of steady, flicker, pulse or strobe"), Scene::try_from(&json).err());
+	}
+}
@@ -37,18 +37,7 @@ pub fn y_step(degrees: i32) -> i32 {
 	Y_STEP[degrees as usize]
 }
 
-pub fn fisheye_correction(degrees: i32) -> i32 {
-	FISHEYE[degrees as usize]
-}
-
-pub fn wall_height(distance: i32) -> i32 {
-	WALL_HEIGHT[distance.min(shared::consts::MAX_RAY_LENGTH) as usize]
-}
-
-pub fn wall_texture_index(height: i32) -> &'static [usize] {
-	let height      = height.clamp(shared::consts::render::WALL_HEIGHT_MIN, shared::consts::render::WALL_HEIGHT_MAX);
-	let true_i      = height - shared::consts::render::WALL_HEIGHT_MIN;
-	let head: usize = (true_i * shared::consts::display::PROJECTION_PLANE_HEIGHT) as usize;
-	let tail: usize = head + shared::consts::display::PROJECTION_PLANE_HEIGHT as usize;
-	&WALL_TEXTURE_INDEX[head..tail]
+// distances to the floor and ceiling through each row of the default display, standing up
+pub fn row_distances() -> &'static [i32] {
+	&ROW_DISTANCE
 }
@@ -1,4 +1,5 @@
 use shared::consts;
+use shared::fp;
 use shared::fp::{ ToFixedPoint };
 use shared::radian;
 use proc_macro2::TokenStream;
@@ -6,16 +7,6 @@ use quote::quote;
 
 const TILE_SIZE: f64 = consts::TILE_SIZE as f64;
 
-fn clamp(x: i32, min: i32, max: i32) -> i32 {
-    if x < min {
-        min
-    } else if x > max {
-        max
-    } else {
-        x
-    }
-}
-
 fn declare_trig_tables() -> TokenStream {
     const SIZE: usize    = (consts::ANGLE_360 + 1) as usize;
 
@@ -51,7 +42,7 @@ fn declare_step_tables() -> TokenStream {
 	let mut x_step: [i32; SIZE] = [0; SIZE];
     let mut y_step: [i32; SIZE] = [0; SIZE];
 
-    for i in 0..SIZE {
+    for (i, x_step) in x_step.iter_mut().enumerate() {
         let mut step: f64;
 
         if radian!(i).tan() == 0.0 {
@@ -68,10 +59,10 @@ fn declare_step_tables() -> TokenStream {
             }
         }
 
-        x_step[i] = step.to_fp();
+        *x_step = step.to_fp();
     }
 
-    for i in 0..SIZE {
+    for (i, y_step) in y_step.iter_mut().enumerate() {
         let mut step = TILE_SIZE * radian!(i).tan();
 
         if i >= consts::ANGLE_0.try_into().unwrap() && i < consts::ANGLE_180.try_into().unwrap() {
@@ -82,7 +73,7 @@ fn declare_step_tables() -> TokenStream {
             step = -step;
         }
 
-        y_step[i] = step.to_fp();
+        *y_step = step.to_fp();
     }
 
     quote! {
@@ -91,97 +82,37 @@ fn declare_step_tables() -> TokenStream {
     }
 }
 
-fn declare_fisheye_table() -> TokenStream {
-    const SIZE: usize = consts::PROJECTION_PLANE_WIDTH as usize;
-
-    let mut fisheye: [i32; SIZE] = [0; SIZE];
-
-    for i in 0..SIZE {
-        fisheye[i] = (1.0 / radian!(i as i32 - consts::ANGLE_30 as i32).cos()).to_fp();
-    }
-
-    quote! {
-        static FISHEYE: [i32; #SIZE] = [ #(#fisheye),* ];
-    }
-}
-
-fn declare_wall_height_table() -> TokenStream {
-    const SIZE: usize = (consts::MAX_RAY_LENGTH + 1) as usize;
-
-    let mut wall_height: [i32; SIZE] = [0; SIZE];
-
-    for i in 0..=consts::MAX_RAY_LENGTH {
-        wall_height[i as usize] = clamp(consts::WALL_HEIGHT_SCALE_FACTOR / i.max(1), consts::WALL_HEIGHT_MIN, consts::WALL_HEIGHT_MAX);
-    }
+fn declare_floor_ceiling_tables() -> TokenStream {
+    const SIZE: usize = consts::PROJECTION_PLANE_HEIGHT as usize;
 
-    quote! {
-        static WALL_HEIGHT: [i32; #SIZE] = [ #(#wall_height),* ];
-    }
-}
+    // distance to the projection plane of the default display, with the default field of view
+    let half_width = consts::PROJECTION_PLANE_WIDTH as f64 / 2.0;
+    let pp_distance = ((half_width / radian!(consts::ANGLE_30).tan()) as i32).to_fp();
 
-fn declare_wall_texture_index_table() -> TokenStream {
-    const RANGE: i32  = (consts::WALL_HEIGHT_MAX - consts::WALL_HEIGHT_MIN) + 1;
-    const SIZE: usize = (RANGE * consts::PROJECTION_PLANE_HEIGHT) as usize;
-    
-    let mut wall_texture_index: [usize; SIZE] = [ 0; SIZE ];
-
-    for entry in 0..RANGE {
-        let height = entry + consts::WALL_HEIGHT_MIN;
-        let y_min = std::cmp::max(0, consts::PROJECTION_PLANE_HORIZON - (height >> 1));
-        let y_max = std::cmp::min(consts::PROJECTION_PLANE_HEIGHT - 1, consts::PROJECTION_PLANE_HORIZON + (height >> 1));
-        let step: f64 = consts::TEXTURE_HEIGHT as f64 / height as f64;
-        let mut tex_pos: f64 = (y_min as f64 - consts::PROJECTION_PLANE_HORIZON as f64 + height as f64 / 2.0) * step;
-
-        for index in 0..consts::PROJECTION_PLANE_HEIGHT {
-            let i = (index + entry * consts::PROJECTION_PLANE_HEIGHT) as usize;
-            let tex_y = tex_pos.clamp(0.0, 63.0) as usize;
-            wall_texture_index[i] = tex_y;
-            if index >= y_min && index <= y_max { 
-                tex_pos += step;
-            }
-        }
-    }
+    // standing at the default eye height the floor and ceiling are equally far below and above,
+    // so the one table serves both. Rows are counted away from the horizon, and the row on the
+    // horizon is taken as the first below it to avoid a division by zero
+    let mut row_distance: [i32; SIZE] = [0; SIZE];
 
-    quote! {
-        static WALL_TEXTURE_INDEX: [usize; #SIZE] = [ #(#wall_texture_index),* ];
+    for (row, distance) in row_distance.iter_mut().enumerate() {
+        let row = (row as i32).max(1).to_fp();
+        *distance = fp::floor(fp::mul(pp_distance, fp::div(consts::PLAYER_HEIGHT.to_fp(), row)));
     }
-}
-
-fn declare_floor_ceiling_tables() -> TokenStream {
-    // let mut FLOOR_TEXTURE_Y_RAYS: [i32; PROJECTION_PLANE_WIDTH * PROJECTION_PLANE_CENTRE_Y] = [0; PROJECTION_PLANE_WIDTH * PROJECTION_PLANE_CENTRE_Y];
-    // let mut FLOOR_TEXTURE_X_RAYS: [i32; PROJECTION_PLANE_WIDTH * PROJECTION_PLANE_CENTRE_Y] = [0; PROJECTION_PLANE_WIDTH * PROJECTION_PLANE_CENTRE_Y];
-
-    // for y in (PROJECTION_PLANE_CENTRE_Y + 1)..PROJECTION_PLANE_HEIGHT {
-    //     let ratio: f64 = PLAYER_HEIGHT as f64 / (y - PROJECTION_PLANE_CENTRE_Y) as f64;
-    //     for sweep in 0..PROJECTION_PLANE_WIDTH {
-    //         let distance = DISTANCE_TO_PROJECTION_PLANE as f64 * ratio * fisheye[sweep];
-    //     }
-    // }
 
-    // var diagonalDistance=Math.floor((this.fPlayerDistanceToTheProjectionPlane * ratio) * (this.fFishTable[castColumn]));
-
-    // var yEnd = Math.floor(diagonalDistance * this.fSinTable[castArc]);
-    // var xEnd = Math.floor(diagonalDistance * this.fCosTable[castArc]);
     quote! {
-
+        static ROW_DISTANCE: [i32; #SIZE] = [ #(#row_distance),* ];
     }
 }
 
 #[proc_macro]
 pub fn insert_lookup_tables(_input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-	let trig_tables              = declare_trig_tables();
-	let step_tables              = declare_step_tables();
-    let fisheye_table            = declare_fisheye_table();
-    let wall_height_table        = declare_wall_height_table();
-    let floor_ceiling_tables     = declare_floor_ceiling_tables();
-    let wall_texture_index_table = declare_wall_texture_index_table();
+	let trig_tables = declare_trig_tables();
+	let step_tables = declare_step_tables();
+	let floor_ceiling_tables = declare_floor_ceiling_tables();
 
 	proc_macro::TokenStream::from(quote! {
 		#trig_tables
 		#step_tables
-        #fisheye_table
-        #wall_height_table
-        #floor_ceiling_tables
-        #wall_texture_index_table
+		#floor_ceiling_tables
 	})
 }
\ No newline at end of file
@@ -1,7 +1,8 @@
-pub const PROJECTION_PLANE_HEIGHT: i32      = 200;
-pub const PROJECTION_PLANE_WIDTH: i32       = 320;
-pub const PROJECTION_PLANE_HORIZON: i32     = PROJECTION_PLANE_HEIGHT >> 1;
-pub const DISTANCE_TO_PROJECTION_PLANE: i32 = 277;
+// default display size, the actual size is chosen at runtime by the render config
+pub const PROJECTION_PLANE_HEIGHT: i32  = 200;
+pub const PROJECTION_PLANE_WIDTH: i32   = 320;
+pub const PROJECTION_PLANE_HORIZON: i32 = PROJECTION_PLANE_HEIGHT >> 1;
 
-pub const TILE_SIZE: i32    = 64;
+pub const TILE_SHIFT: i32   = 6; // tiles are a power of two in size, so world coordinates shift down to tiles
+pub const TILE_SIZE: i32    = 1 << TILE_SHIFT;
 pub const FP_TILE_SIZE: i32 = TILE_SIZE << 16;
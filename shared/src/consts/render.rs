@@ -1,12 +1,17 @@
 pub const TEXTURE_WIDTH: usize  = 64;
 pub const TEXTURE_HEIGHT: usize = 64;
 
-pub const WALL_HEIGHT_SCALE_FACTOR: i32 = 18000;
-pub const WALL_HEIGHT_MIN: i32          = 8;
-pub const WALL_HEIGHT_MAX: i32          = 640;
+// walls closer than this are drawn as if they were this far away, which bounds the projected wall height
+pub const WALL_DISTANCE_MIN: i32 = 28;
 
 pub const MAX_RAY_LENGTH: i32    = 2048;
 pub const FP_MAX_RAY_LENGTH: i32 = MAX_RAY_LENGTH << 16;
 
+// time taken in milliseconds for a door to slide fully open or closed
+pub const DOOR_OPEN_TIME: i32 = 1000;
+
+// time taken in milliseconds for a push wall to slide one tile
+pub const PUSH_WALL_MOVE_TIME: i32 = 1000;
+
 pub const WALL_HEIGHT: i32   = 64;
 pub const PLAYER_HEIGHT: i32 = WALL_HEIGHT >> 1;
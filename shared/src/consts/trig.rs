@@ -1,6 +1,6 @@
-use crate::consts::display::PROJECTION_PLANE_WIDTH;
-
-pub const ANGLE_60:  i32 = PROJECTION_PLANE_WIDTH;
+// angular resolution of the lookup tables. This is independent of the display
+// so that viewports up to 1280 columns wide can still cast a distinct ray per column
+pub const ANGLE_60:  i32 = 1280;
 
 pub const ANGLE_0:   i32 = 0;
 pub const ANGLE_5:   i32 = ANGLE_60 / 12;
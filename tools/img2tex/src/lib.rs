@@ -9,16 +9,13 @@ fn transform_tile(outbuf: &mut Vec<u8>, imgbuf: &[u8], tile_id: u32, tile_size:
 	for x in x_min..x_max {
 		for y in y_min..y_max {
 			let p = (x + y * img_width as usize) * 4;
-			outbuf.push(imgbuf[p + 0]);
-			outbuf.push(imgbuf[p + 1]);
-			outbuf.push(imgbuf[p + 2]);
-			outbuf.push(imgbuf[p + 3]);
+			outbuf.extend_from_slice(&imgbuf[p..p + 4]);
 		}
 	}
 }
 
 pub fn convert_file(fname: &String, tile_size: u32) -> Vec<u8> {
-	let file = File::open(fname).expect(format!("unable to open '{}'", fname).as_str());
+	let file = File::open(fname).unwrap_or_else(|_| panic!("unable to open '{}'", fname));
 	// The decoder is a build for reader and can be used to set various decoding options
 	// via `Transformations`. The default output transformation is `Transformations::IDENTITY`.
 	let mut decoder = png::Decoder::new(file);
@@ -29,7 +26,7 @@ pub fn convert_file(fname: &String, tile_size: u32) -> Vec<u8> {
 	let img_width = header.width;
 	let img_height = header.height;
 	assert!(img_width > 0 && img_height > 0, "image has zero width or height");
-	assert!(img_width % tile_size == 0 && img_height % tile_size == 0, "tile sheet height and width must be even multiples of tile_size");
+	assert!(img_width.is_multiple_of(tile_size) && img_height.is_multiple_of(tile_size), "tile sheet height and width must be even multiples of tile_size");
 	assert!(header.color_type == png::ColorType::Rgba, "currently only support pngs in rgba format");
 	assert!(header.bit_depth == png::BitDepth::Eight, "currently only support pngs with 8-bit depth");
 
@@ -45,11 +42,10 @@ pub fn convert_file(fname: &String, tile_size: u32) -> Vec<u8> {
 
 	// Grab the bytes of the image.
 	let bytes = &buf[..info.buffer_size()];
-	let mut result = Vec::new();
-	result.reserve(info.buffer_size());
+	let mut result = Vec::with_capacity(info.buffer_size());
 
 	for tile_id in 0..num_tiles {
-		transform_tile(&mut result, &bytes, tile_id, tile_size, img_width);
+		transform_tile(&mut result, bytes, tile_id, tile_size, img_width);
 	}
 
 	result
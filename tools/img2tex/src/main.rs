@@ -28,5 +28,5 @@ fn main() {
 	let args: Cli = Cli::parse();
 	let image_data = convert_file(&args.image_file, args.tile_size);
 	let encoded: String = base64_encode(&image_data);
-	fs::write(&args.output, encoded).expect(format!("unable to write output to '{}'", &args.output).as_str());
+	fs::write(&args.output, encoded).unwrap_or_else(|_| panic!("unable to write output to '{}'", &args.output));
 }